@@ -1,6 +1,12 @@
 use seed::{prelude::*, *};
-use shared::{Event, EventData, SyncData};
-use std::rc::Rc;
+use shared::{
+    chat,
+    checksum::Sections,
+    desync::{DesyncReport, RecentEvents},
+    AdminAction, Event, EventData, EventId, GameError, ResumeToken, State, SyncData, UserId,
+    PROTOCOL_VERSION,
+};
+use std::{rc::Rc, time::Duration};
 
 const WS_URL: &str = "ws://127.0.0.1:3000/game/ws";
 
@@ -12,6 +18,18 @@ pub struct Model {
     web_socket: WebSocket,
     web_socket_reconnector: Option<StreamHandle>,
     state: Option<SyncData>,
+    // The state right after the last tick, which a dropped connection can be
+    // resumed from. Unknown until the first tick after a sync.
+    checkpoint: Option<State>,
+    // The events applied since the last sync, for desync reports.
+    recent: RecentEvents,
+    resume_token: Option<ResumeToken>,
+    next_event_id: EventId,
+    outdated: bool,
+    chat: Vec<(UserId, String)>,
+    // Together with the tick they were applied at.
+    announcements: Vec<(u64, String)>,
+    chat_input: String,
 }
 
 // ------ ------
@@ -23,6 +41,14 @@ fn init(_: Url, orders: &mut impl Orders<Msg>) -> Model {
         web_socket: create_websocket(orders),
         web_socket_reconnector: None,
         state: None,
+        checkpoint: None,
+        recent: RecentEvents::default(),
+        resume_token: None,
+        next_event_id: 0,
+        outdated: false,
+        chat: Vec::new(),
+        announcements: Vec::new(),
+        chat_input: String::new(),
     }
 }
 
@@ -38,7 +64,16 @@ pub enum Msg {
     ReconnectWebSocket(usize),
     SendGameEvent(Event),
     ReceiveGameEvent(EventData),
+    GameEventRejected(EventId, GameError),
+    Hello(u32, ResumeToken),
     InitGameState(SyncData),
+    ResumeGameState,
+    ProtocolMismatch,
+    ChatInputChanged(String),
+    SendChat,
+    ReceiveChat(UserId, String),
+    Throttled(Option<EventId>, Duration),
+    Checksum(u64, Sections),
 }
 
 fn update(msg: Msg, mut model: &mut Model, orders: &mut impl Orders<Msg>) {
@@ -77,17 +112,122 @@ fn update(msg: Msg, mut model: &mut Model, orders: &mut impl Orders<Msg>) {
             model.web_socket = create_websocket(orders);
         }
         Msg::SendGameEvent(event) => {
-            let serialized = rmp_serde::to_vec(&shared::Req::Event(event)).unwrap();
+            let event_id = model.next_event_id;
+            model.next_event_id += 1;
+            let serialized = rmp_serde::to_vec(&shared::Req::Event(event_id, event)).unwrap();
             model.web_socket.send_bytes(&serialized).unwrap();
         }
         Msg::ReceiveGameEvent(event) => {
             if let Some(SyncData { state, .. }) = &mut model.state {
+                if let Event::Admin(AdminAction::Broadcast(text)) = &event.event {
+                    model.announcements.push((state.tick, text.clone()));
+                }
+                let is_tick = matches!(event.event, Event::Tick);
+                model.recent.push(event.clone());
                 state.update(event);
+                if is_tick {
+                    model.checkpoint = Some(state.clone());
+                }
             }
         }
+        Msg::GameEventRejected(event_id, error) => {
+            log!("Event", event_id, "was rejected:", error);
+        }
+        Msg::Hello(protocol_version, resume_token) => {
+            if protocol_version != PROTOCOL_VERSION {
+                orders.send_msg(Msg::ProtocolMismatch);
+                return;
+            }
+
+            let req = match &model.checkpoint {
+                Some(checkpoint) if model.resume_token == Some(resume_token) => {
+                    shared::Req::Resume {
+                        token: resume_token,
+                        last_tick: checkpoint.tick,
+                    }
+                }
+                _ => shared::Req::Join,
+            };
+            model.resume_token = Some(resume_token);
+            let serialized = rmp_serde::to_vec(&req).unwrap();
+            model.web_socket.send_bytes(&serialized).unwrap();
+        }
         Msg::InitGameState(sync_data) => {
+            // Events of the current tick may already be part of the state.
+            model.checkpoint = None;
+            model.recent.clear();
             model.state = Some(sync_data);
         }
+        Msg::ResumeGameState => {
+            // The missed events follow, starting at the checkpoint.
+            if let (Some(SyncData { state, .. }), Some(checkpoint)) =
+                (&mut model.state, &model.checkpoint)
+            {
+                *state = checkpoint.clone();
+                model.recent.clear();
+                // The announcements from the checkpoint on are sent again.
+                model
+                    .announcements
+                    .retain(|(tick, _)| *tick < checkpoint.tick);
+            }
+        }
+        Msg::ChatInputChanged(text) => {
+            model.chat_input = text;
+        }
+        Msg::SendChat => {
+            if !chat::validate(&model.chat_input) {
+                return;
+            }
+            // The server drops these silently, so keep the input instead.
+            let allowed = model.state.as_ref().is_some_and(|SyncData { state, .. }| {
+                state.settings.chat.apply(&model.chat_input).is_some()
+            });
+            if !allowed {
+                log!("Message is not allowed in this world");
+                return;
+            }
+
+            let text = std::mem::take(&mut model.chat_input);
+            let serialized = rmp_serde::to_vec(&shared::Req::Chat {
+                channel: chat::Channel::Global,
+                text,
+            })
+            .unwrap();
+            model.web_socket.send_bytes(&serialized).unwrap();
+        }
+        Msg::ReceiveChat(from, text) => {
+            model.chat.push((from, text));
+        }
+        Msg::Throttled(event_id, retry_after) => {
+            if let Some(event_id) = event_id {
+                log!("Event", event_id, "was dropped");
+            }
+            log!("Sending too fast, retry in", retry_after.as_millis(), "ms");
+        }
+        Msg::Checksum(tick, sections) => {
+            if let Some(SyncData { state, .. }) = &model.state {
+                if state.tick == tick && state.sections() != sections {
+                    let report = DesyncReport::new(tick, sections, state, &model.recent);
+                    let diverged = report.diverged();
+                    log!("State diverged from the server at tick", tick, "in", diverged);
+                    for req in [shared::Req::ReportDesync(report), shared::Req::Join] {
+                        let serialized = rmp_serde::to_vec(&req).unwrap();
+                        model.web_socket.send_bytes(&serialized).unwrap();
+                    }
+                }
+            }
+        }
+        Msg::ProtocolMismatch => {
+            log!("Server uses a different protocol version");
+            model.outdated = true;
+            model.state = None;
+            model.checkpoint = None;
+            model.web_socket_reconnector = None;
+            model
+                .web_socket
+                .close(None, Some("protocol version mismatch"))
+                .unwrap();
+        }
     }
 }
 
@@ -113,14 +253,40 @@ fn decode_message(message: WebSocketMessage, msg_sender: Rc<dyn Fn(Option<Msg>)>
                 .await
                 .expect("WebsocketError on binary data");
 
-            let msg: shared::Res = rmp_serde::from_slice(&bytes).unwrap();
-            match msg {
-                shared::Res::Event(event) => {
+            match rmp_serde::from_slice(&bytes) {
+                Ok(shared::Res::Event(event, _)) => {
                     msg_sender(Some(Msg::ReceiveGameEvent(event)));
                 }
-                shared::Res::Sync(sync) => {
+                Ok(shared::Res::Rejected(event_id, error)) => {
+                    msg_sender(Some(Msg::GameEventRejected(event_id, error)));
+                }
+                Ok(shared::Res::Hello {
+                    protocol_version,
+                    resume_token,
+                }) => {
+                    msg_sender(Some(Msg::Hello(protocol_version, resume_token)));
+                }
+                Ok(shared::Res::Sync(sync)) => {
                     msg_sender(Some(Msg::InitGameState(sync)));
                 }
+                Ok(shared::Res::Resumed) => {
+                    msg_sender(Some(Msg::ResumeGameState));
+                }
+                Ok(shared::Res::Chat { from, text, .. }) => {
+                    msg_sender(Some(Msg::ReceiveChat(from, text)));
+                }
+                Ok(shared::Res::Throttled {
+                    event_id,
+                    retry_after,
+                }) => {
+                    msg_sender(Some(Msg::Throttled(event_id, retry_after)));
+                }
+                Ok(shared::Res::Checksum { tick, sections }) => {
+                    msg_sender(Some(Msg::Checksum(tick, sections)));
+                }
+                Err(_) => {
+                    msg_sender(Some(Msg::ProtocolMismatch));
+                }
             }
         });
     }
@@ -131,9 +297,20 @@ fn decode_message(message: WebSocketMessage, msg_sender: Rc<dyn Fn(Option<Msg>)>
 // ------ ------
 
 fn view(model: &Model) -> Vec<Node<Msg>> {
-    if let Some(SyncData { user_id, state }) = &model.state {
+    if model.outdated {
+        vec![p!["A new version of the game is available, please reload the page."]]
+    } else if let Some(SyncData { user_id, state, .. }) = &model.state {
         vec![
             h1!["WebSocket example"],
+            p![{
+                let time = state.world_time();
+                format!(
+                    "Day {} ({:?}), {}",
+                    time.day + 1,
+                    time.season,
+                    if time.is_night() { "night" } else { "day" }
+                )
+            }],
             button![
                 ev(Ev::Click, move |_| Msg::SendGameEvent(Event::Increment)),
                 "Increment Counter"
@@ -146,6 +323,26 @@ fn view(model: &Model) -> Vec<Node<Msg>> {
                 "Increment Private Counter"
             ],
             p![state.cnt_private.get(user_id)],
+            ul![model
+                .announcements
+                .iter()
+                .map(|(_, text)| li![format!("Announcement: {}", text)])],
+            h2!["Chat"],
+            ul![model
+                .chat
+                .iter()
+                .map(|(from, text)| li![format!("Player {}: {}", from, text)])],
+            input![
+                attrs! {
+                    At::Value => model.chat_input,
+                    At::MaxLength => chat::MAX_MESSAGE_LENGTH,
+                },
+                input_ev(Ev::Input, Msg::ChatInputChanged),
+                keyboard_ev(Ev::KeyDown, |event| {
+                    IF!(event.key() == "Enter" => Msg::SendChat)
+                }),
+            ],
+            button![ev(Ev::Click, |_| Msg::SendChat), "Send"],
         ]
     } else {
         vec![p!["Loading ..."]]
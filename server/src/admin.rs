@@ -0,0 +1,185 @@
+use askama::Template;
+use askama_axum::{IntoResponse, Response};
+use axum::{response::Redirect, Extension, Form};
+use axum_sessions::async_session::Session;
+use serde::Deserialize;
+use shared::{chat, runner::TickControl, AdminAction, UserId};
+use sqlx::SqlitePool;
+
+use crate::{game::GameState, ServerError};
+
+pub async fn admin_id(session: &Session, pool: &SqlitePool) -> Result<Option<UserId>, ServerError> {
+    let result: Option<(UserId,)> = sqlx::query_as(
+        r#"
+            SELECT admins.user_id
+            FROM sessions
+            JOIN admins ON sessions.user_id = admins.user_id
+            WHERE session_id = $1
+        "#,
+    )
+    .bind(&session.id())
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(result.map(|(user_id,)| user_id))
+}
+
+#[derive(Template, Default)]
+#[template(path = "admin.html")]
+pub struct AdminTemplate {
+    tick: u64,
+    paused: bool,
+    speed: f64,
+    // Only available when built with the `debug` feature.
+    rewind: bool,
+}
+
+pub async fn get_admin(
+    Extension(session): Extension<Session>,
+    Extension(pool): Extension<SqlitePool>,
+    Extension(game_state): Extension<GameState>,
+) -> Result<Response, ServerError> {
+    if admin_id(&session, &pool).await?.is_some() {
+        let (tick, paused, speed) = game_state.tick_status().await;
+        Ok(AdminTemplate {
+            tick,
+            paused,
+            speed,
+            rewind: cfg!(feature = "debug"),
+        }
+        .into_response())
+    } else {
+        Ok(Redirect::to("/login").into_response())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TickCommand {
+    Pause,
+    Resume,
+    Step,
+    Speed,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TickForm {
+    command: TickCommand,
+    speed: Option<f64>,
+}
+
+pub async fn post_tick(
+    Form(form): Form<TickForm>,
+    Extension(session): Extension<Session>,
+    Extension(pool): Extension<SqlitePool>,
+    Extension(game_state): Extension<GameState>,
+) -> Result<Response, ServerError> {
+    if admin_id(&session, &pool).await?.is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+
+    let control = match form.command {
+        TickCommand::Pause => Some(TickControl::Pause),
+        TickCommand::Resume => Some(TickControl::Resume),
+        TickCommand::Step => Some(TickControl::Step),
+        TickCommand::Speed => form.speed.map(TickControl::SetSpeed),
+    };
+    if let Some(control) = control {
+        game_state.control(control);
+    }
+
+    Ok(Redirect::to("/admin").into_response())
+}
+
+#[cfg(feature = "debug")]
+#[derive(Debug, Deserialize)]
+pub struct RewindForm {
+    tick: u64,
+}
+
+#[cfg(feature = "debug")]
+pub async fn post_rewind(
+    Form(form): Form<RewindForm>,
+    Extension(session): Extension<Session>,
+    Extension(pool): Extension<SqlitePool>,
+    Extension(game_state): Extension<GameState>,
+) -> Result<Response, ServerError> {
+    if admin_id(&session, &pool).await?.is_none() {
+        return Ok(Redirect::to("/login").into_response());
+    }
+
+    game_state.rewind(form.tick);
+
+    Ok(Redirect::to("/admin").into_response())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionCommand {
+    SetCounter,
+    SetPrivateCounter,
+    Ban,
+    Unban,
+    Mute,
+    Unmute,
+    Kick,
+    Broadcast,
+    AbandonedAfter,
+    ChatPolicy,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActionForm {
+    command: ActionCommand,
+    user_id: Option<UserId>,
+    value: Option<u32>,
+    text: Option<String>,
+    // Only present if the checkbox is checked.
+    strip_links: Option<String>,
+}
+
+pub async fn post_action(
+    Form(form): Form<ActionForm>,
+    Extension(session): Extension<Session>,
+    Extension(pool): Extension<SqlitePool>,
+    Extension(game_state): Extension<GameState>,
+) -> Result<Response, ServerError> {
+    let admin_id = if let Some(admin_id) = admin_id(&session, &pool).await? {
+        admin_id
+    } else {
+        return Ok(Redirect::to("/login").into_response());
+    };
+
+    let action = match form.command {
+        ActionCommand::SetCounter => form.value.map(AdminAction::SetCounter),
+        ActionCommand::SetPrivateCounter => form
+            .user_id
+            .zip(form.value)
+            .map(|(user_id, value)| AdminAction::SetPrivateCounter(user_id, value)),
+        ActionCommand::Ban => form.user_id.map(AdminAction::BanPlayer),
+        ActionCommand::Unban => form.user_id.map(AdminAction::UnbanPlayer),
+        ActionCommand::Mute => form.user_id.map(AdminAction::MutePlayer),
+        ActionCommand::Unmute => form.user_id.map(AdminAction::UnmutePlayer),
+        ActionCommand::Kick => form.user_id.map(AdminAction::KickPlayer),
+        ActionCommand::Broadcast => form.text.map(AdminAction::Broadcast),
+        ActionCommand::AbandonedAfter => form
+            .value
+            .map(|days| AdminAction::SetAbandonedAfter(days as u64)),
+        ActionCommand::ChatPolicy => Some(AdminAction::SetChatPolicy(chat::Policy {
+            blocked_words: form
+                .text
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|word| !word.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            strip_links: form.strip_links.is_some(),
+        })),
+    };
+    if let Some(action) = action {
+        game_state.admin(admin_id, action);
+    }
+
+    Ok(Redirect::to("/admin").into_response())
+}
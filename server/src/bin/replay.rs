@@ -0,0 +1,77 @@
+// Replays the journal in `data.db` from an empty world and prints every event
+// and what changed with every tick.
+//
+// Usage: replay [--until <tick>] [--break <tick>]...
+//
+// At a breakpoint the full state is printed and the replay waits for enter.
+
+use shared::{
+    replay::{self, Replay},
+    Event, EventData, State,
+};
+use sqlx::{sqlite::SqliteConnectOptions, SqlitePool};
+use std::{io::BufRead, str::FromStr};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut until = None;
+    let mut breakpoints = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let tick = args.next().ok_or("missing tick")?.parse::<u64>()?;
+        match arg.as_str() {
+            "--until" => until = Some(tick),
+            "--break" => breakpoints.push(tick),
+            _ => return Err(format!("unknown argument {}", arg).into()),
+        }
+    }
+
+    let options = SqliteConnectOptions::from_str("sqlite:data.db")?;
+    let pool = SqlitePool::connect_with(options).await?;
+
+    let rows: Vec<(i64, Vec<u8>)> = sqlx::query_as(
+        r#"
+            SELECT tick, data
+            FROM journal
+            WHERE world = 'world'
+            ORDER BY event_id
+        "#,
+    )
+    .fetch_all(&pool)
+    .await?;
+    let mut journal = Vec::with_capacity(rows.len());
+    for (tick, data) in rows {
+        journal.push((tick as u64, rmp_serde::from_slice::<EventData>(&data[..])?));
+    }
+
+    let mut replay = Replay::new(State::default(), journal);
+    let mut before = replay.state().clone();
+    while until.is_none_or(|until| replay.state().tick < until) {
+        let event = match replay.step() {
+            Some(event) => event,
+            None => break,
+        };
+        if !matches!(event.event, Event::Tick) {
+            println!("  {:?} by {:?}", event.event, event.user_id);
+            continue;
+        }
+
+        let state = replay.state();
+        println!("tick {}", state.tick);
+        for change in replay::diff(&before, state) {
+            println!("    {}", change);
+        }
+
+        if breakpoints.contains(&state.tick) {
+            println!("{:#?}", state);
+            println!("press enter to continue");
+            std::io::stdin().lock().read_line(&mut String::new())?;
+        }
+        before = state.clone();
+    }
+
+    println!("replayed up to tick {}", replay.state().tick);
+
+    Ok(())
+}
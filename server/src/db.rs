@@ -43,6 +43,53 @@ pub async fn setup() -> Result<SqlitePool, Box<dyn std::error::Error>> {
     .execute(&mut transaction)
     .await?;
 
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS journal (
+            event_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            world TEXT NOT NULL,
+            tick INTEGER NOT NULL,
+            data BLOB NOT NULL
+        )
+    "#,
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS clocks (
+            world TEXT PRIMARY KEY,
+            tick INTEGER NOT NULL,
+            time INTEGER NOT NULL
+        )
+    "#,
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS tick_controls (
+            world TEXT PRIMARY KEY,
+            paused INTEGER NOT NULL,
+            speed REAL NOT NULL
+        )
+    "#,
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS admins (
+            user_id INTEGER PRIMARY KEY REFERENCES users(user_id)
+        )
+    "#,
+    )
+    .execute(&mut transaction)
+    .await?;
+
     transaction.commit().await?;
 
     Ok(pool)
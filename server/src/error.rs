@@ -10,6 +10,8 @@ pub enum ServerError {
     //AxumFormRejection(#[from] axum::extract::rejection::FormRejection),
     #[error(transparent)]
     SqliteError(#[from] sqlx::Error),
+    #[error("you are banned from this world")]
+    Banned,
 }
 
 impl IntoResponse for ServerError {
@@ -22,6 +24,7 @@ impl IntoResponse for ServerError {
             ServerError::SqliteError(err) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response()
             }
+            ServerError::Banned => (StatusCode::FORBIDDEN, self.to_string()).into_response(),
         }
     }
 }
@@ -7,24 +7,114 @@ use axum::{
 };
 use axum_sessions::async_session::Session;
 use futures_util::{sink::SinkExt, stream::StreamExt};
-use shared::{EventData, UserId, SyncData, Event};
+use shared::{
+    chat,
+    migrate::{VersionedSnapshot, STATE_VERSION},
+    runner::{Processed, TickControl, WorldRunner},
+    snapshot::Snapshot,
+    AdminAction, Event, EventData, EventId, ResumeToken, SyncData, UserId,
+};
+#[cfg(feature = "debug")]
+use shared::history::History;
 use sqlx::SqlitePool;
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
 use tokio::{sync::{broadcast, mpsc, RwLock}, time};
 
-use crate::ServerError;
+use crate::{
+    limit::{RateLimiter, Throttle},
+    ServerError,
+};
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
 
 #[derive(Clone)]
 pub struct GameState(Arc<GameStateImpl>);
 
 struct GameStateImpl {
-    state: RwLock<shared::State>,
-    res_sender: broadcast::Sender<EventData>,
-    req_sender: mpsc::UnboundedSender<EventData>,
+    runner: RwLock<WorldRunner<Origin>>,
+    res_sender: broadcast::Sender<Broadcast>,
+    // Tells every connection to send a full sync, for changes of the state
+    // that the broadcast events don't reflect.
+    resync_sender: broadcast::Sender<()>,
+    req_sender: mpsc::UnboundedSender<Request>,
+    recent: Mutex<Recent>,
+    resume_token: ResumeToken,
+}
+
+// The events applied during the last `RESUME_TICKS` ticks, together with the
+// tick they were applied at, so reconnecting clients only get what they
+// missed. A single buffer is shared by all users, it is filtered per user when
+// a connection resumes.
+struct Recent {
+    // The buffer holds every event applied from this tick on.
+    since: u64,
+    events: VecDeque<(u64, EventData)>,
+}
+
+const RESUME_TICKS: u64 = 300;
+
+pub enum Request {
+    // An event waiting to be applied, together with the connection it came
+    // from so it can be told when the event gets rejected.
+    Event { event: EventData, origin: Origin },
+    Control(TickControl),
+    #[cfg(feature = "debug")]
+    Rewind(u64),
+}
+
+type Origin = Option<(EventId, mpsc::UnboundedSender<shared::Res>)>;
+
+// A response for every connection, together with the connection the event in
+// it came from. Event ids are chosen by the clients, so only the originating
+// connection gets to see its id, see `Broadcast::for_connection`.
+#[derive(Clone)]
+pub struct Broadcast {
+    res: shared::Res,
+    origin: Origin,
+}
+
+impl Broadcast {
+    fn new(res: shared::Res) -> Self {
+        Broadcast { res, origin: None }
+    }
+
+    // Unlike a reply, this can't overtake the broadcasts sent before it.
+    fn for_connection(self, reply: &mpsc::UnboundedSender<shared::Res>) -> shared::Res {
+        match (self.res, self.origin) {
+            (shared::Res::Event(event, _), Some((event_id, origin))) if origin.same_channel(reply) => {
+                shared::Res::Event(event, Some(event_id))
+            }
+            (res, _) => res,
+        }
+    }
 }
 
+// How many ticks apart the snapshots of the rewind history are, and how many
+// of them are kept.
+#[cfg(feature = "debug")]
+const HISTORY_INTERVAL: u64 = 60;
+#[cfg(feature = "debug")]
+const HISTORY_CAPACITY: usize = 10;
+
+// A full snapshot is written every this many ticks, in between only the
+// events are appended to the journal.
+const SNAPSHOT_INTERVAL: u64 = 60;
+
+// Every this many ticks, clients get the checksum of their state to detect
+// desyncs.
+const CHECKSUM_INTERVAL: u64 = 10;
+
 impl GameState {
-    async fn load_game(pool: &SqlitePool) -> Option<shared::State> {
+    async fn load_snapshot(pool: &SqlitePool) -> Option<Snapshot> {
         let result: Result<Option<(Vec<u8>,)>, _> = sqlx::query_as(
             r#"
                 SELECT data
@@ -35,82 +125,442 @@ impl GameState {
         .fetch_optional(pool)
         .await;
 
+        result.unwrap().map(|(data,)| {
+            let versioned = VersionedSnapshot::decode(&data[..]).unwrap();
+
+            if versioned.version() != STATE_VERSION {
+                tracing::info!(
+                    "migrating world from version {} to {}",
+                    versioned.version(),
+                    STATE_VERSION
+                );
+            }
+            versioned.migrate()
+        })
+    }
+
+    async fn store_snapshot(pool: &SqlitePool, snapshot: Snapshot) {
+        let tick = snapshot.tick;
+
+        sqlx::query(
+            r#"
+                INSERT OR REPLACE INTO worlds (name, data)
+                VALUES ('world', $1)
+            "#,
+        )
+        .bind(rmp_serde::to_vec(&VersionedSnapshot::from(snapshot)).unwrap())
+        .execute(pool)
+        .await
+        .unwrap();
+
+        GameState::store_clock(pool, tick).await;
+    }
+
+    // Remembers that the world was at `tick` just now. Has to be written
+    // whenever the tick rate changes, since catching up assumes the world kept
+    // running at the same rate after the clock was written.
+    async fn store_clock(pool: &SqlitePool, tick: u64) {
+        sqlx::query(
+            r#"
+                INSERT OR REPLACE INTO clocks (world, tick, time)
+                VALUES ('world', $1, $2)
+            "#,
+        )
+        .bind(tick as i64)
+        .bind(now())
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    async fn load_tick_controls(pool: &SqlitePool) -> Option<(bool, f64)> {
+        let result: Result<Option<(bool, f64)>, _> = sqlx::query_as(
+            r#"
+                SELECT paused, speed
+                FROM tick_controls
+                WHERE world = 'world'
+            "#,
+        )
+        .fetch_optional(pool)
+        .await;
+
+        result.unwrap()
+    }
+
+    async fn store_tick_controls(pool: &SqlitePool, tick: u64, paused: bool, speed: f64) {
+        GameState::store_clock(pool, tick).await;
+
+        sqlx::query(
+            r#"
+                INSERT OR REPLACE INTO tick_controls (world, paused, speed)
+                VALUES ('world', $1, $2)
+            "#,
+        )
+        .bind(paused)
+        .bind(speed)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    // Fast forwards over the ticks that were missed while the server was down,
+    // running at `speed` unless it was paused.
+    async fn catch_up(pool: &SqlitePool, state: &mut shared::State, paused: bool, speed: f64) {
+        let result: Result<Option<(i64, i64)>, _> = sqlx::query_as(
+            r#"
+                SELECT tick, time
+                FROM clocks
+                WHERE world = 'world'
+            "#,
+        )
+        .fetch_optional(pool)
+        .await;
+
+        if let Some((tick, time)) = result.unwrap() {
+            let elapsed = if paused {
+                0
+            } else {
+                let millis = (now() - time).max(0) as f64;
+                (millis * speed / shared::TICK_INTERVAL.as_millis() as f64) as i64
+            };
+            let missed = (tick + elapsed) - state.tick as i64;
+
+            if missed > 0 {
+                tracing::info!("catching up on {} missed ticks", missed);
+                state.advance_ticks(missed as u64);
+                GameState::store_snapshot(pool, state.snapshot()).await;
+            }
+        }
+    }
+
+    // The admins table is the source of truth, changes to it take effect on
+    // the next start.
+    async fn load_admins(pool: &SqlitePool) -> BTreeSet<UserId> {
+        let result: Result<Vec<(UserId,)>, _> = sqlx::query_as(
+            r#"
+                SELECT user_id
+                FROM admins
+            "#,
+        )
+        .fetch_all(pool)
+        .await;
+
+        result.unwrap().into_iter().map(|(user_id,)| user_id).collect()
+    }
+
+    // Forgets the journaled events that were undone by a rewind, the snapshot
+    // contains everything up to the current state.
+    #[cfg(feature = "debug")]
+    async fn reset_journal(pool: &SqlitePool, state: &shared::State) {
+        GameState::store_snapshot(pool, state.snapshot()).await;
+
+        sqlx::query(
+            r#"
+                DELETE FROM journal
+                WHERE world = 'world' AND tick >= $1
+            "#,
+        )
+        .bind(state.tick as i64)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    async fn load_journal(pool: &SqlitePool, tick: u64) -> Vec<EventData> {
+        let result: Result<Vec<(Vec<u8>,)>, _> = sqlx::query_as(
+            r#"
+                SELECT data
+                FROM journal
+                WHERE world = 'world' AND tick >= $1
+                ORDER BY event_id
+            "#,
+        )
+        .bind(tick as i64)
+        .fetch_all(pool)
+        .await;
+
         result
             .unwrap()
+            .into_iter()
             .map(|(data,)| rmp_serde::from_slice(&data[..]).unwrap())
+            .collect()
     }
 
-    async fn store_game(pool: &SqlitePool, state: &shared::State) {
+    async fn append_journal(pool: &SqlitePool, tick: u64, event: &EventData) {
         sqlx::query(
             r#"
-                INSERT OR REPLACE INTO worlds (name, data)
-                VALUES ('world', $1)
+                INSERT INTO journal (world, tick, data)
+                VALUES ('world', $1, $2)
             "#,
         )
-        .bind(rmp_serde::to_vec(state).unwrap())
+        .bind(tick as i64)
+        .bind(rmp_serde::to_vec(event).unwrap())
         .execute(pool)
         .await
         .unwrap();
     }
 
     pub async fn new(pool: SqlitePool) -> GameState {
-        let (req_sender, mut req_receiver) = mpsc::unbounded_channel::<EventData>();
-        let (res_sender, _res_receiver) = broadcast::channel::<EventData>(128);
+        let (req_sender, mut req_receiver) = mpsc::unbounded_channel::<Request>();
+        let (res_sender, _res_receiver) = broadcast::channel::<Broadcast>(128);
+        let (resync_sender, _resync_receiver) = broadcast::channel::<()>(1);
 
-        let req_sender_clone = req_sender.clone();
-
-        let game = RwLock::new(GameState::load_game(&pool).await.unwrap_or_default());
+        let snapshot = GameState::load_snapshot(&pool)
+            .await
+            .unwrap_or_else(|| shared::State::default().snapshot());
+        let journal = GameState::load_journal(&pool, snapshot.tick).await;
+        let mut game = shared::State::replay(snapshot, journal);
+        let (paused, speed) = GameState::load_tick_controls(&pool).await.unwrap_or((false, 1.0));
+        GameState::catch_up(&pool, &mut game, paused, speed).await;
+        game.admins = GameState::load_admins(&pool).await;
+        let recent = Recent {
+            since: game.tick,
+            events: VecDeque::new(),
+        };
+        let mut runner = WorldRunner::new(game);
+        runner.control(TickControl::SetSpeed(speed));
+        if paused {
+            runner.control(TickControl::Pause);
+        }
+        #[cfg(feature = "debug")]
+        runner.record_history(History::new(HISTORY_INTERVAL, HISTORY_CAPACITY));
         let game_state = Arc::new(GameStateImpl {
-            state: game,
+            runner: RwLock::new(runner),
             res_sender,
+            resync_sender,
             req_sender,
+            recent: Mutex::new(recent),
+            // Changes with every restart, which discards the buffered events.
+            resume_token: now() as ResumeToken,
         });
         let game_state_clone = game_state.clone();
 
-        tokio::spawn(async move {
-            let mut interval = time::interval(Duration::from_secs(1));
-
-            loop {
-                interval.tick().await;
-
-                req_sender_clone.send(EventData {
-                    event: Event::Tick,
-                    user_id: None,
-                }).unwrap();
-            }
-        });
-
         tokio::spawn(async move {
             let GameStateImpl {
-                state: game,
+                runner,
                 res_sender,
+                #[cfg(feature = "debug")]
+                resync_sender,
+                recent,
                 ..
             } = &*game_state_clone;
 
-            while let Some(event) = req_receiver.recv().await {
-                let mut game = game.write().await;
-                res_sender.send(event.clone()).ok();
-                game.update(event);
-                GameState::store_game(&pool, &*game).await;
+            let mut interval = time::interval(runner.read().await.tick_interval());
+            let mut last_tick = interval.tick().await;
+
+            loop {
+                let mut runner = tokio::select! {
+                    request = req_receiver.recv() => {
+                        let mut runner = runner.write().await;
+                        match request {
+                            Some(Request::Event { event, origin }) => {
+                                runner.push(event, origin);
+                            }
+                            Some(Request::Control(control)) => {
+                                runner.control(control);
+                                GameState::store_tick_controls(
+                                    &pool,
+                                    runner.state().tick,
+                                    runner.paused(),
+                                    runner.speed(),
+                                )
+                                .await;
+                                if runner.tick_interval() != interval.period() {
+                                    let period = runner.tick_interval();
+                                    interval = time::interval_at(last_tick + period, period);
+                                }
+                            }
+                            #[cfg(feature = "debug")]
+                            Some(Request::Rewind(tick)) => {
+                                if runner.rewind_to(tick) {
+                                    GameState::reset_journal(&pool, runner.state()).await;
+                                    {
+                                        let mut recent = recent.lock().unwrap();
+                                        recent.since = runner.state().tick;
+                                        recent.events.clear();
+                                    }
+                                    // The clients are ahead of the server now.
+                                    resync_sender.send(()).ok();
+                                }
+                            }
+                            None => break,
+                        }
+                        runner
+                    }
+                    now = interval.tick() => {
+                        let mut runner = runner.write().await;
+                        runner.advance(now - last_tick);
+                        last_tick = now;
+                        runner
+                    }
+                };
+
+                while let Some(processed) = runner.process_next() {
+                    let Processed { tick, event, origin, result, .. } = processed;
+
+                    if let Err(error) = result {
+                        if let Some((event_id, reply)) = origin {
+                            reply.send(shared::Res::Rejected(event_id, error)).ok();
+                        }
+                        continue;
+                    }
+
+                    GameState::append_journal(&pool, tick, &event).await;
+                    let is_tick = matches!(event.event, Event::Tick);
+                    {
+                        let mut recent = recent.lock().unwrap();
+                        recent.events.push_back((tick, event.clone()));
+                        let current = runner.state().tick;
+                        while let Some(&(oldest, _)) = recent.events.front() {
+                            if oldest + RESUME_TICKS >= current {
+                                break;
+                            }
+                            recent.events.pop_front();
+                            recent.since = oldest + 1;
+                        }
+                    }
+                    res_sender
+                        .send(Broadcast {
+                            res: shared::Res::Event(event, None),
+                            origin,
+                        })
+                        .ok();
+                    if is_tick && runner.state().tick.is_multiple_of(SNAPSHOT_INTERVAL) {
+                        GameState::store_snapshot(&pool, runner.state().snapshot()).await;
+                    }
+                }
             }
         });
 
         GameState(game_state)
     }
 
-    pub async fn new_connection(
+    pub fn request_sender(&self) -> mpsc::UnboundedSender<Request> {
+        self.0.req_sender.clone()
+    }
+
+    pub fn resyncs(&self) -> broadcast::Receiver<()> {
+        self.0.resync_sender.subscribe()
+    }
+
+    pub fn resume_token(&self) -> ResumeToken {
+        self.0.resume_token
+    }
+
+    // Subscribes while holding the lock, so no event gets lost or applied
+    // twice between the sync and the first broadcast.
+    pub async fn join(&self, user_id: UserId) -> (SyncData, broadcast::Receiver<Broadcast>) {
+        let runner = self.0.runner.read().await;
+        (runner.sync_data(user_id), self.0.res_sender.subscribe())
+    }
+
+    // Returns the events applied from `last_tick` on that the user is allowed
+    // to see, or `None` if they are not buffered anymore.
+    pub async fn resume(
         &self,
         user_id: UserId,
-    ) -> (
-        shared::State,
-        mpsc::UnboundedSender<EventData>,
-        broadcast::Receiver<EventData>,
-    ) {
-        (
-            self.0.state.read().await.view(user_id),
-            self.0.req_sender.clone(),
-            self.0.res_sender.subscribe(),
-        )
+        token: ResumeToken,
+        last_tick: u64,
+    ) -> Option<(Vec<EventData>, broadcast::Receiver<Broadcast>)> {
+        let runner = self.0.runner.read().await;
+        let recent = self.0.recent.lock().unwrap();
+        if token != self.0.resume_token || last_tick < recent.since || last_tick > runner.state().tick {
+            return None;
+        }
+
+        let missed = recent
+            .events
+            .iter()
+            .filter(|(tick, event)| *tick >= last_tick && event.filter(user_id))
+            .map(|(_, event)| event.clone())
+            .collect();
+        Some((missed, self.0.res_sender.subscribe()))
+    }
+
+    pub fn admin(&self, admin_id: UserId, action: AdminAction) {
+        let event = EventData {
+            event: Event::Admin(action),
+            user_id: Some(admin_id),
+        };
+        self.0
+            .req_sender
+            .send(Request::Event {
+                event,
+                origin: None,
+            })
+            .ok();
+    }
+
+    // Keeps an active player from being considered inactive.
+    pub async fn seen(&self, user_id: UserId) {
+        if self.0.runner.read().await.state().needs_seen(user_id) {
+            let event = EventData {
+                event: Event::Seen(user_id),
+                user_id: None,
+            };
+            self.0
+                .req_sender
+                .send(Request::Event {
+                    event,
+                    origin: None,
+                })
+                .ok();
+        }
+    }
+
+    pub fn control(&self, control: TickControl) {
+        self.0.req_sender.send(Request::Control(control)).ok();
+    }
+
+    #[cfg(feature = "debug")]
+    pub fn rewind(&self, tick: u64) {
+        self.0.req_sender.send(Request::Rewind(tick)).ok();
+    }
+
+    pub async fn tick_status(&self) -> (u64, bool, f64) {
+        let runner = self.0.runner.read().await;
+        (runner.state().tick, runner.paused(), runner.speed())
+    }
+
+    // Events are only ever applied all at once at a tick boundary, so the state
+    // read here is always the one right after a tick.
+    pub async fn checksum(&self, user_id: UserId) -> Option<shared::Res> {
+        let runner = self.0.runner.read().await;
+        let tick = runner.state().tick;
+        if tick.is_multiple_of(CHECKSUM_INTERVAL) {
+            let sections = runner.state().view(user_id).sections();
+            Some(shared::Res::Checksum { tick, sections })
+        } else {
+            None
+        }
+    }
+
+    pub async fn is_banned(&self, user_id: UserId) -> bool {
+        self.0.runner.read().await.state().banned.contains(&user_id)
+    }
+
+    // Messages of muted players are dropped without telling them, banned
+    // players are dropped as well in case they are still connected. Clients
+    // know the chat policy and check it before sending.
+    pub async fn chat(&self, from: UserId, channel: chat::Channel, text: String) {
+        let runner = self.0.runner.read().await;
+        if runner.state().muted.contains(&from) || runner.state().banned.contains(&from) {
+            return;
+        }
+        let text = match runner.state().settings.chat.apply(&text) {
+            Some(text) => text,
+            None => return,
+        };
+        let tick = runner.state().tick;
+        self.0
+            .res_sender
+            .send(Broadcast::new(shared::Res::Chat {
+                channel,
+                from,
+                text,
+                tick,
+            }))
+            .ok();
     }
 }
 
@@ -132,31 +582,121 @@ pub async fn ws_handler(
     .await?;
 
     if let Some((user_id,)) = result {
+        if game_state.is_banned(user_id).await {
+            return Err(ServerError::Banned);
+        }
+
         Ok(ws.on_upgrade(move |socket: WebSocket| async move {
-            let (state, sender, mut receiver) = game_state.new_connection(user_id).await;
+            let sender = game_state.request_sender();
+            let (reply_sender, mut reply_receiver) = mpsc::unbounded_channel::<shared::Res>();
+            let (resync_sender, mut resync_receiver) = mpsc::unbounded_channel::<()>();
+            let mut resyncs = game_state.resyncs();
             let (mut sink, mut stream) = socket.split();
-    
-            let msg = rmp_serde::to_vec(&shared::Res::Sync(SyncData {
-                user_id,
-                state
-            })).unwrap();
+
+            let hello = shared::Res::Hello {
+                protocol_version: shared::PROTOCOL_VERSION,
+                resume_token: game_state.resume_token(),
+            };
+            let msg = rmp_serde::to_vec(&hello).unwrap();
             if sink.send(Message::Binary(msg)).await.is_err() {
                 return;
             }
-    
+
+            // Wait for the client to either join or resume.
+            let resume = loop {
+                match stream.next().await {
+                    Some(Ok(Message::Binary(msg))) => match rmp_serde::from_slice(&msg) {
+                        Ok(shared::Req::Resume { token, last_tick }) => break Some((token, last_tick)),
+                        _ => break None,
+                    },
+                    Some(Ok(_)) => {}
+                    _ => return,
+                }
+            };
+            let resumed = match resume {
+                Some((token, last_tick)) => game_state.resume(user_id, token, last_tick).await,
+                None => None,
+            };
+            let (greeting, mut receiver) = match resumed {
+                Some((missed, receiver)) => {
+                    let greeting = std::iter::once(shared::Res::Resumed)
+                        .chain(missed.into_iter().map(|event| shared::Res::Event(event, None)))
+                        .collect();
+                    (greeting, receiver)
+                }
+                None => {
+                    let (sync, receiver) = game_state.join(user_id).await;
+                    (vec![shared::Res::Sync(sync)], receiver)
+                }
+            };
+            for res in greeting {
+                let msg = rmp_serde::to_vec(&res).unwrap();
+                if sink.send(Message::Binary(msg)).await.is_err() {
+                    return;
+                }
+            }
+            // Banned while the connection was being set up.
+            if game_state.is_banned(user_id).await {
+                return;
+            }
+            game_state.seen(user_id).await;
+
             tokio::select!(
                 _ = async {
+                    let mut limiter = RateLimiter::new();
+
                     while let Some(msg) = stream.next().await {
                         if let Ok(msg) = msg {
                             if let Message::Binary(msg) = msg {
                                 println!("client {} sent data", user_id);
-                                let req: shared::Req = rmp_serde::from_slice(&msg).unwrap();
+                                let req: shared::Req = match rmp_serde::from_slice(&msg) {
+                                    Ok(req) => req,
+                                    Err(_) => break,
+                                };
+                                match limiter.check(&req) {
+                                    Ok(()) => {}
+                                    Err(Throttle::Wait(retry_after)) => {
+                                        let event_id = match &req {
+                                            shared::Req::Event(event_id, _) => Some(*event_id),
+                                            _ => None,
+                                        };
+                                        reply_sender.send(shared::Res::Throttled { event_id, retry_after }).ok();
+                                        continue;
+                                    }
+                                    Err(Throttle::Disconnect) => {
+                                        tracing::info!("disconnecting client {} for flooding", user_id);
+                                        break;
+                                    }
+                                }
                                 match req {
-                                    shared::Req::Event(event) => {
-                                        if sender.send(EventData {event, user_id: Some(user_id) }).is_err() {
+                                    shared::Req::Event(event_id, event) => {
+                                        game_state.seen(user_id).await;
+                                        let request = Request::Event {
+                                            event: EventData { event, user_id: Some(user_id) },
+                                            origin: Some((event_id, reply_sender.clone())),
+                                        };
+                                        if sender.send(request).is_err() {
                                             break;
                                         }
                                     }
+                                    shared::Req::Chat { channel, text } => {
+                                        if chat::validate(&text) {
+                                            game_state.chat(user_id, channel, text).await;
+                                        }
+                                    }
+                                    shared::Req::Join => {
+                                        resync_sender.send(()).ok();
+                                    }
+                                    shared::Req::Resume { .. } => {}
+                                    shared::Req::ReportDesync(report) => {
+                                        tracing::warn!(
+                                            "client {} diverged at tick {} in {:?}, last events: {:?}",
+                                            user_id,
+                                            report.tick,
+                                            report.diverged(),
+                                            report.events
+                                        );
+                                    }
                                 }  
                             }
                         } else {
@@ -166,24 +706,45 @@ pub async fn ws_handler(
                 } => {},
                 _ = async {
                     loop {
-                        match receiver.recv().await {
-                            Ok(event) => {
-                                if event.filter(user_id) {
-                                    let msg = rmp_serde::to_vec(&shared::Res::Event(event)).unwrap();
+                        let res = tokio::select! {
+                            res = receiver.recv() => res.map(|broadcast| Some(broadcast.for_connection(&reply_sender))),
+                            Some(res) = reply_receiver.recv() => Ok(Some(res)),
+                            Some(()) = resync_receiver.recv() => Ok(None),
+                            Ok(()) | Err(broadcast::error::RecvError::Lagged(_)) = resyncs.recv() => Ok(None),
+                        };
+
+                        match res {
+                            Ok(Some(res)) => {
+                                if res.filter(user_id) {
+                                    let msg = rmp_serde::to_vec(&res).unwrap();
                                     if sink.send(Message::Binary(msg)).await.is_err() {
                                         break;
                                     }
                                 }
+                                if let shared::Res::Event(EventData { event: Event::Admin(action), .. }, _) = &res {
+                                    if action.disconnects() == Some(user_id) {
+                                        break;
+                                    }
+                                }
+                                if matches!(res, shared::Res::Event(EventData { event: Event::Tick, .. }, _)) {
+                                    if let Some(checksum) = game_state.checksum(user_id).await {
+                                        let msg = rmp_serde::to_vec(&checksum).unwrap();
+                                        if sink.send(Message::Binary(msg)).await.is_err() {
+                                            break;
+                                        }
+                                    }
+                                }
                             },
-                            // If a broadcast message is discarded that wasn't seen yet by this receiver,
-                            // request a full game state update.
-                            Err(broadcast::error::RecvError::Lagged(_)) => {
-                                let (state, _, new_receiver) = game_state.new_connection(user_id).await;
+                            // If the client asked for it, the server told so, or a broadcast message
+                            // is discarded that wasn't seen yet by this receiver, send a full game
+                            // state update. A ban might have been among the skipped events.
+                            Ok(None) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                                if game_state.is_banned(user_id).await {
+                                    break;
+                                }
+                                let (sync, new_receiver) = game_state.join(user_id).await;
                                 receiver = new_receiver;
-                                let msg = rmp_serde::to_vec(&shared::Res::Sync(SyncData {
-                                    user_id,
-                                    state
-                                })).unwrap();
+                                let msg = rmp_serde::to_vec(&shared::Res::Sync(sync)).unwrap();
                                 if sink.send(Message::Binary(msg)).await.is_err() {
                                     break;
                                 }
@@ -208,6 +769,7 @@ pub struct GameTemplate {}
 pub async fn get_game(
     Extension(session): Extension<Session>,
     Extension(pool): Extension<SqlitePool>,
+    Extension(game_state): Extension<GameState>,
 ) -> Result<Response, ServerError> {
     let result: Option<(UserId,)> = sqlx::query_as(
         r#"
@@ -220,7 +782,11 @@ pub async fn get_game(
     .fetch_optional(&pool)
     .await?;
 
-    if let Some((_user_id,)) = result {
+    if let Some((user_id,)) = result {
+        if game_state.is_banned(user_id).await {
+            return Err(ServerError::Banned);
+        }
+
         Ok(GameTemplate::default().into_response())
     } else {
         Ok(Redirect::to("/login").into_response())
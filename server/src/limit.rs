@@ -0,0 +1,149 @@
+use shared::{chat, Event, Req};
+use std::{collections::HashMap, time::Duration};
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limit {
+    // How many requests can be sent in a row.
+    pub burst: u32,
+    // How long it takes to regain one request.
+    pub refill: Duration,
+}
+
+// Every kind of request gets its own bucket, so flooding one kind doesn't
+// block the others.
+fn limit(req: &Req) -> (&'static str, Limit) {
+    match req {
+        Req::Event(_, Event::Increment) => (
+            "increment",
+            Limit {
+                burst: 20,
+                refill: Duration::from_millis(100),
+            },
+        ),
+        Req::Event(_, Event::IncrementPrivate) => (
+            "increment_private",
+            Limit {
+                burst: 20,
+                refill: Duration::from_millis(100),
+            },
+        ),
+        Req::Event(_, Event::Admin(_)) => (
+            "admin",
+            Limit {
+                burst: 10,
+                refill: Duration::from_secs(1),
+            },
+        ),
+        // Always rejected, a client has no reason to send these at all.
+        Req::Event(_, Event::Tick | Event::Seen(_)) => (
+            "server",
+            Limit {
+                burst: 1,
+                refill: Duration::from_secs(60),
+            },
+        ),
+        Req::Chat { .. } => (
+            "chat",
+            Limit {
+                burst: 3,
+                refill: chat::MESSAGE_INTERVAL,
+            },
+        ),
+        Req::ReportDesync(_) => (
+            "report",
+            Limit {
+                burst: 1,
+                refill: Duration::from_secs(60),
+            },
+        ),
+        Req::Join | Req::Resume { .. } => (
+            "join",
+            Limit {
+                burst: 1,
+                refill: Duration::from_secs(10),
+            },
+        ),
+    }
+}
+
+// Every throttled request uses up one of these, a client that runs out of
+// them gets disconnected.
+const VIOLATIONS: Limit = Limit {
+    burst: 20,
+    refill: Duration::from_secs(1),
+};
+
+struct TokenBucket {
+    limit: Limit,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: Limit) -> Self {
+        TokenBucket {
+            limit,
+            tokens: limit.burst as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    // Takes a token, or returns how long it takes until one is available.
+    fn take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let regained = (now - self.updated).as_secs_f64() / self.limit.refill.as_secs_f64();
+        self.tokens = (self.tokens + regained).min(self.limit.burst as f64);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(self.limit.refill.mul_f64(1.0 - self.tokens))
+        }
+    }
+}
+
+pub enum Throttle {
+    // The request is dropped, the client may retry after this long.
+    Wait(Duration),
+    Disconnect,
+}
+
+// Limits the requests of a single connection.
+pub struct RateLimiter {
+    buckets: HashMap<&'static str, TokenBucket>,
+    violations: TokenBucket,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        RateLimiter {
+            buckets: HashMap::new(),
+            violations: TokenBucket::new(VIOLATIONS),
+        }
+    }
+
+    pub fn check(&mut self, req: &Req) -> Result<(), Throttle> {
+        let (kind, limit) = limit(req);
+        let bucket = self
+            .buckets
+            .entry(kind)
+            .or_insert_with(|| TokenBucket::new(limit));
+
+        bucket.take().map_err(|retry_after| {
+            if self.violations.take().is_ok() {
+                Throttle::Wait(retry_after)
+            } else {
+                Throttle::Disconnect
+            }
+        })
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter::new()
+    }
+}
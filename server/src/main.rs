@@ -1,14 +1,16 @@
+mod admin;
 mod auth;
 mod db;
 mod error;
 mod game;
 mod index;
+mod limit;
 
 use error::*;
 
 use axum::{
     http::StatusCode,
-    routing::{get, get_service},
+    routing::{get, get_service, post},
     Extension, Router,
 };
 use axum_sessions::{async_session::MemoryStore, SessionLayer};
@@ -55,6 +57,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/", get(index::get_index))
         .route("/game", get(game::get_game))
         .route("/game/ws", get(game::ws_handler))
+        .route("/admin", get(admin::get_admin))
+        .route("/admin/tick", post(admin::post_tick))
+        .route("/admin/action", post(admin::post_action));
+    #[cfg(feature = "debug")]
+    let app = app.route("/admin/rewind", post(admin::post_rewind));
+    let app = app
         .route(
             "/register",
             get(auth::register::get_register).post(auth::register::post_register),
@@ -0,0 +1,104 @@
+// How the cost of a tick, of syncing a player and of saving the world grows
+// with the number of players. Run with `cargo bench -p shared`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use shared::{
+    calendar::TICKS_PER_DAY, migrate::VersionedSnapshot, Event, EventData, State, SyncData,
+};
+
+const PLAYERS: [u64; 3] = [10, 1_000, 100_000];
+const SEED: u64 = 42;
+
+fn event(event: Event, user_id: Option<i64>) -> EventData {
+    EventData { event, user_id }
+}
+
+fn update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update");
+    for n in PLAYERS {
+        let state = State::synthetic(n, SEED);
+        group.bench_with_input(BenchmarkId::new("tick", n), &state, |b, state| {
+            b.iter_batched_ref(
+                || state.clone(),
+                |state| state.update(event(Event::Tick, None)),
+                BatchSize::LargeInput,
+            )
+        });
+
+        // The last tick of a day, which reclaims abandoned counters.
+        let mut state = state;
+        state.tick += TICKS_PER_DAY - 1 - state.tick % TICKS_PER_DAY;
+        group.bench_with_input(BenchmarkId::new("new_day", n), &state, |b, state| {
+            b.iter_batched_ref(
+                || state.clone(),
+                |state| state.update(event(Event::Tick, None)),
+                BatchSize::LargeInput,
+            )
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("increment_private", n),
+            &state,
+            |b, state| {
+                b.iter_batched_ref(
+                    || state.clone(),
+                    |state| state.update(event(Event::IncrementPrivate, Some(2))),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+// The throughput is the encoded size, so the report shows how the size grows
+// as well.
+fn sync(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sync");
+    for n in PLAYERS {
+        let state = State::synthetic(n, SEED);
+        let sync = SyncData {
+            user_id: 2,
+            state: state.view(2),
+        };
+        group.throughput(Throughput::Bytes(
+            rmp_serde::to_vec(&sync).unwrap().len() as u64
+        ));
+        group.bench_with_input(BenchmarkId::new("view", n), &state, |b, state| {
+            b.iter(|| {
+                let sync = SyncData {
+                    user_id: 2,
+                    state: state.view(2),
+                };
+                rmp_serde::to_vec(&sync).unwrap()
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("checksum", n), &state, |b, state| {
+            b.iter(|| state.view(2).checksum())
+        });
+    }
+    group.finish();
+}
+
+fn snapshot(c: &mut Criterion) {
+    let mut group = c.benchmark_group("snapshot");
+    for n in PLAYERS {
+        let state = State::synthetic(n, SEED);
+        let snapshot = VersionedSnapshot::from(state.snapshot());
+        group.throughput(Throughput::Bytes(
+            rmp_serde::to_vec(&snapshot).unwrap().len() as u64,
+        ));
+        group.bench_with_input(BenchmarkId::new("store", n), &state, |b, state| {
+            b.iter(|| rmp_serde::to_vec(&VersionedSnapshot::from(state.snapshot())).unwrap())
+        });
+
+        group.bench_with_input(BenchmarkId::new("checksum", n), &state, |b, state| {
+            b.iter(|| state.checksum())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, update, sync, snapshot);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+use crate::State;
+use serde::{Deserialize, Serialize};
+
+// With one tick per second, a day lasts 24 minutes and a season a week of days.
+pub const TICKS_PER_DAY: u64 = 24 * 60;
+pub const DAYS_PER_SEASON: u64 = 7;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldTime {
+    pub tick: u64,
+    pub day: u64,
+    pub season: Season,
+}
+
+impl WorldTime {
+    pub fn from_tick(tick: u64) -> Self {
+        let day = tick / TICKS_PER_DAY;
+        let season = match (day / DAYS_PER_SEASON) % 4 {
+            0 => Season::Spring,
+            1 => Season::Summer,
+            2 => Season::Autumn,
+            _ => Season::Winter,
+        };
+
+        WorldTime { tick, day, season }
+    }
+
+    // Ticks since the start of the current day, which begins at midnight.
+    pub fn time_of_day(&self) -> u64 {
+        self.tick % TICKS_PER_DAY
+    }
+
+    // How bright it is outside, from 0.0 at midnight to 1.0 at noon.
+    pub fn daylight(&self) -> f32 {
+        let angle = self.time_of_day() as f32 / TICKS_PER_DAY as f32 * std::f32::consts::TAU;
+        (1.0 - angle.cos()) / 2.0
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.daylight() < 0.25
+    }
+}
+
+impl State {
+    pub fn world_time(&self) -> WorldTime {
+        WorldTime::from_tick(self.tick)
+    }
+}
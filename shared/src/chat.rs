@@ -0,0 +1,103 @@
+use crate::UserId;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const MAX_MESSAGE_LENGTH: usize = 256;
+// How long it takes a connection to regain one message. Short bursts are
+// allowed, see the rate limits of the server.
+pub const MESSAGE_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Channel {
+    Global,
+    Private(UserId),
+}
+
+impl Channel {
+    pub fn receives(&self, from: UserId, receiver: UserId) -> bool {
+        match self {
+            Channel::Global => true,
+            Channel::Private(to) => *to == receiver || from == receiver,
+        }
+    }
+}
+
+pub fn validate(text: &str) -> bool {
+    !text.trim().is_empty() && text.chars().count() <= MAX_MESSAGE_LENGTH
+}
+
+// What operators allow in the chat of their world, part of its settings.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Policy {
+    // Messages that contain any of these, ignoring case, are dropped.
+    pub blocked_words: Vec<String>,
+    // Removes every word that looks like a link.
+    pub strip_links: bool,
+}
+
+impl Policy {
+    // Blocked words can't be empty, since they would block every message.
+    pub fn validate(&self) -> bool {
+        self.blocked_words
+            .iter()
+            .all(|word| !word.trim().is_empty() && validate(word))
+    }
+
+    // Returns the message as the other players get to see it, or `None` if
+    // it isn't allowed in this world.
+    pub fn apply(&self, text: &str) -> Option<String> {
+        let lowercase = text.to_lowercase();
+        if self
+            .blocked_words
+            .iter()
+            .any(|word| lowercase.contains(&word.to_lowercase()))
+        {
+            return None;
+        }
+
+        let text = if self.strip_links {
+            text.split_whitespace()
+                .filter(|word| !is_link(word))
+                .collect::<Vec<_>>()
+                .join(" ")
+        } else {
+            text.to_owned()
+        };
+        validate(&text).then_some(text)
+    }
+}
+
+fn is_link(word: &str) -> bool {
+    let word = word.to_lowercase();
+    ["http://", "https://", "www."]
+        .iter()
+        .any(|prefix| word.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Policy;
+
+    #[test]
+    fn policy() {
+        let policy = Policy {
+            blocked_words: vec!["Darn".to_owned()],
+            strip_links: true,
+        };
+        assert_eq!(policy.apply("oh DARN it"), None);
+        assert_eq!(
+            policy.apply("see https://example.com and www.example.com now"),
+            Some("see and now".to_owned())
+        );
+        assert_eq!(policy.apply("http://example.com"), None);
+        assert_eq!(
+            Policy::default().apply("http://example.com").as_deref(),
+            Some("http://example.com")
+        );
+        assert!(!Policy {
+            blocked_words: vec![" ".to_owned()],
+            strip_links: false,
+        }
+        .validate());
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{Settings, State};
+use serde::{Deserialize, Serialize};
+
+// FNV-1a, which unlike `DefaultHasher` gives the same result on every
+// platform and in every run.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+// Hashes of the parts of the state that change independently, so a desync
+// can be narrowed down to the parts that diverged.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sections {
+    pub clock: u64,
+    // The shared and the private counters.
+    pub counters: u64,
+    // Admins, bans and mutes.
+    pub moderation: u64,
+    pub last_seen: u64,
+    pub settings: u64,
+}
+
+impl Sections {
+    fn named(&self) -> [(&'static str, u64); 5] {
+        let Sections {
+            clock,
+            counters,
+            moderation,
+            last_seen,
+            settings,
+        } = *self;
+
+        [
+            ("clock", clock),
+            ("counters", counters),
+            ("moderation", moderation),
+            ("last_seen", last_seen),
+            ("settings", settings),
+        ]
+    }
+
+    // Equal for equal states, see `State::checksum`.
+    pub fn checksum(&self) -> u64 {
+        let mut hash = Fnv::new();
+        for (_, section) in self.named() {
+            hash.write(&section.to_le_bytes());
+        }
+        hash.0
+    }
+
+    // The names of the sections that differ.
+    pub fn diff(&self, other: &Sections) -> Vec<&'static str> {
+        self.named()
+            .into_iter()
+            .zip(other.named())
+            .filter(|((_, a), (_, b))| a != b)
+            .map(|((name, _), _)| name)
+            .collect()
+    }
+}
+
+impl State {
+    // Maps are hashed in key order, since their iteration order differs
+    // between instances.
+    pub fn sections(&self) -> Sections {
+        let State {
+            cnt,
+            cnt_private,
+            tick,
+            admins,
+            banned,
+            muted,
+            last_seen,
+            settings,
+        } = self;
+
+        let mut clock = Fnv::new();
+        clock.write(&tick.to_le_bytes());
+
+        let mut counters = Fnv::new();
+        counters.write(&cnt.to_le_bytes());
+        let mut private: Vec<_> = cnt_private.iter().collect();
+        private.sort_unstable();
+        counters.write(&(private.len() as u64).to_le_bytes());
+        for (user_id, cnt) in private {
+            counters.write(&user_id.to_le_bytes());
+            counters.write(&cnt.to_le_bytes());
+        }
+
+        let mut moderation = Fnv::new();
+        for set in [admins, banned, muted] {
+            moderation.write(&(set.len() as u64).to_le_bytes());
+            for user_id in set {
+                moderation.write(&user_id.to_le_bytes());
+            }
+        }
+
+        let mut seen = Fnv::new();
+        seen.write(&(last_seen.len() as u64).to_le_bytes());
+        for (user_id, tick) in last_seen {
+            seen.write(&user_id.to_le_bytes());
+            seen.write(&tick.to_le_bytes());
+        }
+
+        let mut rules = Fnv::new();
+        let Settings {
+            abandoned_after_days,
+            chat,
+        } = settings;
+        rules.write(&abandoned_after_days.to_le_bytes());
+        rules.write(&(chat.blocked_words.len() as u64).to_le_bytes());
+        for word in &chat.blocked_words {
+            rules.write(&(word.len() as u64).to_le_bytes());
+            rules.write(word.as_bytes());
+        }
+        rules.write(&[chat.strip_links as u8]);
+
+        Sections {
+            clock: clock.0,
+            counters: counters.0,
+            moderation: moderation.0,
+            last_seen: seen.0,
+            settings: rules.0,
+        }
+    }
+
+    // Equal states have equal checksums on the server and on every client, so
+    // comparing them detects desyncs.
+    pub fn checksum(&self) -> u64 {
+        self.sections().checksum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        calendar::TICKS_PER_DAY,
+        chat,
+        fixtures::{self, ADMIN},
+        AdminAction, Event, EventData, State, UserId,
+    };
+    use proptest::prelude::*;
+
+    const PLAYERS: [UserId; 3] = [1, 2, 3];
+
+    fn player() -> impl Strategy<Value = UserId> {
+        prop::sample::select(&PLAYERS[..])
+    }
+
+    fn admin_action() -> impl Strategy<Value = AdminAction> {
+        prop_oneof![
+            (u32::MAX - 2..=u32::MAX).prop_map(AdminAction::SetCounter),
+            (player(), 0..100u32)
+                .prop_map(|(user_id, cnt)| { AdminAction::SetPrivateCounter(user_id, cnt) }),
+            player().prop_map(AdminAction::BanPlayer),
+            player().prop_map(AdminAction::UnbanPlayer),
+            player().prop_map(AdminAction::MutePlayer),
+            player().prop_map(AdminAction::UnmutePlayer),
+            player().prop_map(AdminAction::KickPlayer),
+            Just(AdminAction::Broadcast("hello".to_owned())),
+            prop::sample::select(&[0, 1, u64::MAX][..]).prop_map(AdminAction::SetAbandonedAfter),
+            (any::<bool>(), prop::sample::select(&["darn", ""][..])).prop_map(
+                |(strip_links, word)| AdminAction::SetChatPolicy(chat::Policy {
+                    blocked_words: vec![word.to_owned()],
+                    strip_links,
+                })
+            ),
+        ]
+    }
+
+    // Events as the runner would get them, players sending events the server
+    // rejects included.
+    fn event() -> impl Strategy<Value = EventData> {
+        let from = |event: Event, user_id| EventData { event, user_id };
+        prop_oneof![
+            8 => Just(from(Event::Tick, None)),
+            3 => player().prop_map(move |user_id| from(Event::Increment, Some(user_id))),
+            3 => player().prop_map(move |user_id| from(Event::IncrementPrivate, Some(user_id))),
+            1 => player().prop_map(move |user_id| from(Event::Seen(user_id), None)),
+            5 => admin_action().prop_map(move |action| from(Event::Admin(action), Some(ADMIN))),
+        ]
+    }
+
+    // Starts anywhere in the calendar, so short sequences cross days as well.
+    fn world() -> impl Strategy<Value = State> {
+        (0..3 * TICKS_PER_DAY).prop_map(fixtures::empty)
+    }
+
+    // Applies the event like the runner does, if it is valid.
+    fn apply(state: &mut State, event: &EventData) -> bool {
+        let valid = state.validate(event).is_ok();
+        if valid {
+            state.update(event.clone());
+        }
+        valid
+    }
+
+    proptest! {
+        #[test]
+        fn independent_states_agree(
+            world in world(),
+            events in prop::collection::vec(event(), 0..500),
+        ) {
+            let (mut a, mut b) = (world.clone(), world);
+            for event in &events {
+                prop_assert_eq!(apply(&mut a, event), apply(&mut b, event));
+                prop_assert_eq!(a.checksum(), b.checksum());
+            }
+        }
+
+        // Every client only gets the events `filter` lets through, applied to
+        // its view, and has to end up with the same view as the server.
+        #[test]
+        fn client_views_agree(
+            mut server in world(),
+            events in prop::collection::vec(event(), 0..500),
+        ) {
+            let mut clients: Vec<_> = PLAYERS.iter().map(|&p| (p, server.view(p))).collect();
+
+            for event in &events {
+                if !apply(&mut server, event) {
+                    continue;
+                }
+
+                for (player, client) in &mut clients {
+                    if event.filter(*player) {
+                        client.update(event.clone());
+                    }
+                    prop_assert_eq!(
+                        server.view(*player).checksum(),
+                        client.checksum(),
+                        "player {} after {:?}",
+                        player,
+                        event
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sections_pinpoint_divergence() {
+        let state = State::synthetic(10, 1);
+        let mut muted = state.clone();
+        muted.muted.insert(11);
+        assert_eq!(state.sections().diff(&muted.sections()), ["moderation"]);
+
+        let mut ticked = state.clone();
+        ticked.advance_ticks(1);
+        assert_eq!(
+            state.sections().diff(&ticked.sections()),
+            ["clock", "counters"]
+        );
+        assert!(state.sections().diff(&state.clone().sections()).is_empty());
+    }
+}
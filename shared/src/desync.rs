@@ -0,0 +1,50 @@
+use crate::{checksum::Sections, EventData, State};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// How many of the last applied events a report contains.
+pub const REPORT_EVENTS: usize = 64;
+
+// Sent by a client whose state diverged from the server's, so the desync can
+// be narrowed down without reproducing the whole session.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DesyncReport {
+    pub tick: u64,
+    pub server: Sections,
+    pub client: Sections,
+    // The events the client applied last, oldest first.
+    pub events: Vec<EventData>,
+}
+
+impl DesyncReport {
+    pub fn new(tick: u64, server: Sections, state: &State, recent: &RecentEvents) -> Self {
+        DesyncReport {
+            tick,
+            server,
+            client: state.sections(),
+            events: recent.0.iter().cloned().collect(),
+        }
+    }
+
+    // The names of the sections that differ.
+    pub fn diverged(&self) -> Vec<&'static str> {
+        self.client.diff(&self.server)
+    }
+}
+
+// The last events a client applied, kept for desync reports.
+#[derive(Default)]
+pub struct RecentEvents(VecDeque<EventData>);
+
+impl RecentEvents {
+    pub fn push(&mut self, event: EventData) {
+        if self.0.len() == REPORT_EVENTS {
+            self.0.pop_front();
+        }
+        self.0.push_back(event);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{calendar::TICKS_PER_DAY, chat, Settings, State, UserId};
+use std::collections::HashMap;
+
+// Worlds for the tests of this crate and of the server and client.
+
+// The admin of every fixture world.
+pub const ADMIN: UserId = 1;
+
+// A fresh world at `tick`.
+pub fn empty(tick: u64) -> State {
+    let mut state = State {
+        tick,
+        ..Default::default()
+    };
+    state.admins.insert(ADMIN);
+    state
+}
+
+// A world with every field set. Maps have a single entry, so the encoding
+// doesn't depend on the iteration order of `HashMap`.
+pub fn populated() -> State {
+    State {
+        cnt: 42,
+        cnt_private: HashMap::from([(2, 7)]),
+        tick: 1500,
+        admins: [ADMIN].into(),
+        banned: [3].into(),
+        muted: [4].into(),
+        last_seen: [(2, 1440)].into(),
+        settings: Settings {
+            abandoned_after_days: 14,
+            chat: chat::Policy {
+                blocked_words: vec!["darn".to_owned()],
+                strip_links: true,
+            },
+        },
+    }
+}
+
+// Xorshift, so the same seed gives the same world on every platform.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift never leaves zero.
+        Rng(seed.max(1))
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+}
+
+impl State {
+    // A world a few days in, where players `1..=n_players` have private
+    // counters and were seen at some point. `ADMIN` is an admin, a few others
+    // are banned or muted. Everything that grows with the number
+    // of players grows with `n_players`, so it can be used to measure how
+    // large a world gets.
+    pub fn synthetic(n_players: u64, seed: u64) -> State {
+        let mut rng = Rng::new(seed);
+        let mut state = State {
+            cnt: rng.below(u32::MAX as u64) as u32,
+            tick: 3 * TICKS_PER_DAY + rng.below(TICKS_PER_DAY),
+            ..Default::default()
+        };
+
+        for user_id in 1..=n_players as UserId {
+            state.cnt_private.insert(user_id, rng.below(1000) as u32);
+            state.last_seen.insert(user_id, rng.below(state.tick));
+            match rng.below(20) {
+                0 => {
+                    state.banned.insert(user_id);
+                }
+                1 => {
+                    state.muted.insert(user_id);
+                }
+                _ => {}
+            }
+        }
+        state.banned.remove(&ADMIN);
+        state.muted.remove(&ADMIN);
+        state.admins.insert(ADMIN);
+
+        state
+    }
+}
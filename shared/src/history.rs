@@ -0,0 +1,154 @@
+use crate::{Event, EventData, State};
+use std::collections::VecDeque;
+
+struct Snapshot {
+    seq: u64,
+    state: State,
+}
+
+struct Entry {
+    seq: u64,
+    tick: u64,
+    event: EventData,
+}
+
+// Keeps the last few snapshots together with every event applied since the
+// oldest one, so any tick inside that window can be reconstructed.
+pub struct History {
+    interval: u64,
+    capacity: usize,
+    seq: u64,
+    snapshots: VecDeque<Snapshot>,
+    events: VecDeque<Entry>,
+}
+
+impl History {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        History {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            seq: 0,
+            snapshots: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    // Has to be called with the state as it was right before `event` is applied.
+    pub fn record(&mut self, state: &State, event: &EventData) {
+        let boundary =
+            matches!(event.event, Event::Tick) && state.tick.is_multiple_of(self.interval);
+        if self.snapshots.is_empty() || boundary {
+            self.snapshots.push_back(Snapshot {
+                seq: self.seq,
+                state: state.clone(),
+            });
+
+            if self.snapshots.len() > self.capacity {
+                self.snapshots.pop_front();
+                let oldest = self.snapshots.front().unwrap().seq;
+                while matches!(self.events.front(), Some(entry) if entry.seq < oldest) {
+                    self.events.pop_front();
+                }
+            }
+        }
+
+        self.events.push_back(Entry {
+            seq: self.seq,
+            tick: state.tick,
+            event: event.clone(),
+        });
+        self.seq += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.events.clear();
+    }
+
+    pub fn oldest_tick(&self) -> Option<u64> {
+        self.snapshots.front().map(|snapshot| snapshot.state.tick)
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = (u64, &EventData)> {
+        self.events.iter().map(|entry| (entry.tick, &entry.event))
+    }
+}
+
+impl State {
+    // Replaces the state with the one at the start of `tick`, right after the
+    // tick began and before any other event of it was applied, like in a
+    // `Snapshot`. Fails if `tick` lies in the future or isn't covered by the
+    // history anymore.
+    pub fn rewind_to(&mut self, history: &History, tick: u64) -> bool {
+        if tick > self.tick {
+            return false;
+        }
+        // A snapshot contains the events of its own tick, so it has to be
+        // from an earlier one.
+        let snapshot = match history
+            .snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.state.tick < tick)
+        {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+
+        let mut state = snapshot.state.clone();
+        for entry in history
+            .events
+            .iter()
+            .filter(|entry| entry.seq >= snapshot.seq)
+        {
+            if entry.tick >= tick {
+                break;
+            }
+            state.update(entry.event.clone());
+        }
+
+        *self = state;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::History;
+    use crate::{Event, EventData, State};
+
+    fn apply(state: &mut State, history: &mut History, event: Event, user_id: Option<i64>) {
+        let event = EventData { event, user_id };
+        history.record(state, &event);
+        state.update(event);
+    }
+
+    // Every tick, each of two players increments their private counter.
+    fn played(ticks: u64) -> (State, History) {
+        let mut state = State::default();
+        let mut history = History::new(4, 10);
+        for _ in 0..ticks {
+            apply(&mut state, &mut history, Event::Tick, None);
+            apply(&mut state, &mut history, Event::IncrementPrivate, Some(1));
+            apply(&mut state, &mut history, Event::IncrementPrivate, Some(2));
+        }
+        (state, history)
+    }
+
+    #[test]
+    fn rewinds_to_start_of_tick() {
+        let (mut state, history) = played(10);
+        assert!(state.rewind_to(&history, 6));
+        assert_eq!(state.tick, 6);
+        // The increments of tick 6 itself are not applied yet.
+        assert_eq!(state.cnt_private[&1], 5);
+        assert_eq!(state.cnt_private[&2], 5);
+    }
+
+    #[test]
+    fn does_not_rewind_into_future() {
+        let (mut state, history) = played(3);
+        assert!(!state.rewind_to(&history, 100));
+        assert_eq!(state.tick, 3);
+    }
+}
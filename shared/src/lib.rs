@@ -1,6 +1,33 @@
+pub mod calendar;
+pub mod chat;
+pub mod checksum;
+pub mod desync;
+pub mod fixtures;
+#[cfg(feature = "debug")]
+pub mod history;
+pub mod local;
+pub mod migrate;
+pub mod replay;
+pub mod runner;
+pub mod snapshot;
+
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 pub type UserId = i64;
+// Chosen by the client to match acknowledgements and rejections to the events
+// it sent, only meaningful to the sender.
+pub type EventId = u64;
+
+pub const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Has to be bumped whenever `Req`, `Res` or anything they contain changes, so
+// outdated clients notice it instead of failing to decode messages.
+pub const PROTOCOL_VERSION: u32 = 15;
+
+// Identifies a running server instance. Resuming only works against the
+// instance that issued the token, since missed events are only kept in memory.
+pub type ResumeToken = u64;
 
 /*
 pub trait CloneState
@@ -82,13 +109,73 @@ pub struct EventData {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Req {
-    Event(Event),
+    // The first request on every connection, answered with a full `Res::Sync`.
+    // Sent again later on to get a fresh sync after a desync.
+    Join,
+    // Instead of joining, continues a dropped connection. `last_tick` is the
+    // tick of the state the client rolled back to, the events applied from
+    // that tick on are sent again. Answered with a `Res::Sync` if that is not
+    // possible anymore.
+    Resume {
+        token: ResumeToken,
+        last_tick: u64,
+    },
+    Event(EventId, Event),
+    Chat {
+        channel: chat::Channel,
+        text: String,
+    },
+    // Sent by a client that diverged, right before the `Join` that resyncs it.
+    ReportDesync(desync::DesyncReport),
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub enum Res {
+    // The first response on every connection, sent before the client joins.
+    Hello {
+        protocol_version: u32,
+        resume_token: ResumeToken,
+    },
     Sync(SyncData),
-    Event(EventData),
+    // Followed by the missed events, which have to be applied to the state
+    // at the tick the client resumed from.
+    Resumed,
+    // Carries the id of the event only for the connection that sent it.
+    Event(EventData, Option<EventId>),
+    Rejected(EventId, GameError),
+    Chat {
+        channel: chat::Channel,
+        from: UserId,
+        text: String,
+        tick: u64,
+    },
+    // The last request was dropped because the client sent too many of its
+    // kind, it can be sent again after `retry_after`. Carries the id of the
+    // dropped event, if it was one.
+    Throttled {
+        event_id: Option<EventId>,
+        retry_after: Duration,
+    },
+    // The section hashes of the receiver's view of the state at `tick`.
+    Checksum {
+        tick: u64,
+        sections: checksum::Sections,
+    },
+}
+
+impl Res {
+    pub fn filter(&self, receiver: UserId) -> bool {
+        match self {
+            Res::Hello { .. } => true,
+            Res::Sync(sync) => sync.user_id == receiver,
+            Res::Resumed => true,
+            Res::Event(event, _) => event.filter(receiver),
+            Res::Rejected(_, _) => true,
+            Res::Chat { channel, from, .. } => channel.receives(*from, receiver),
+            Res::Throttled { .. } => true,
+            Res::Checksum { .. } => true,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -97,17 +184,150 @@ pub struct SyncData {
     pub state: State,
 }
 
+// Who may emit an event. Every event has to declare one, so the server can
+// authorize all of them in one place before they reach `State::update`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    // Only emitted by the server itself, never accepted from a client.
+    Server,
+    // Any logged in player.
+    Player,
+    // A player listed in `State::admins`, which `State::validate` checks.
+    Admin,
+}
+
+impl EventData {
+    pub fn authorize(&self) -> bool {
+        match self.event.permission() {
+            Permission::Server => self.user_id.is_none(),
+            Permission::Player => self.user_id.is_some(),
+            Permission::Admin => self.user_id.is_some(),
+        }
+    }
+}
+
 // MODIFY EVENTS AND STATE BELOW
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// Rules of a world that admins can change while it runs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    // Players that haven't been seen for this many days of the calendar lose
+    // their private counter. Checked whenever a new day starts.
+    pub abandoned_after_days: u64,
+    pub chat: chat::Policy,
+}
+
+impl Settings {
+    // In ticks. Saturates, so a huge setting means players are never reclaimed.
+    pub fn abandoned_after(&self) -> u64 {
+        self.abandoned_after_days
+            .saturating_mul(calendar::TICKS_PER_DAY)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            abandoned_after_days: 30,
+            chat: chat::Policy::default(),
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
 pub struct State {
     pub cnt: u32,
     pub cnt_private: HashMap<UserId, u32>,
+    #[serde(default)]
+    pub tick: u64,
+    #[serde(default)]
+    pub admins: BTreeSet<UserId>,
+    #[serde(default)]
+    pub banned: BTreeSet<UserId>,
+    #[serde(default)]
+    pub muted: BTreeSet<UserId>,
+    // When each player was last seen, up to a day of the calendar.
+    #[serde(default)]
+    pub last_seen: BTreeMap<UserId, u64>,
+    #[serde(default)]
+    pub settings: Settings,
 }
 
 impl State {
+    // Has to accept an event only if `update` can apply it without panicking.
+    pub fn validate(&self, event: &EventData) -> Result<(), GameError> {
+        if !event.authorize() {
+            return Err(GameError::Unauthorized);
+        }
+        if event.event.permission() == Permission::Admin
+            && !self.admins.contains(&event.user_id.unwrap())
+        {
+            return Err(GameError::Unauthorized);
+        }
+        // The server disconnects banned players, this only catches events that
+        // were already on their way.
+        if event
+            .user_id
+            .is_some_and(|user_id| self.banned.contains(&user_id))
+        {
+            return Err(GameError::Unauthorized);
+        }
+
+        match &event.event {
+            Event::Increment => {
+                if self.cnt == u32::MAX {
+                    return Err(GameError::CounterOverflow);
+                }
+            }
+            Event::IncrementPrivate => {
+                let cnt = self.cnt_private.get(&event.user_id.unwrap());
+                if cnt == Some(&u32::MAX) {
+                    return Err(GameError::CounterOverflow);
+                }
+            }
+            Event::Tick => {}
+            // Players are only seen once a day, even if the server asked for
+            // it several times before the first one was applied.
+            Event::Seen(user_id) => {
+                if !self.needs_seen(*user_id) {
+                    return Err(GameError::AlreadySeen);
+                }
+            }
+            Event::Admin(action) => match action {
+                AdminAction::BanPlayer(user_id)
+                | AdminAction::MutePlayer(user_id)
+                | AdminAction::KickPlayer(user_id) => {
+                    if self.admins.contains(user_id) {
+                        return Err(GameError::Unauthorized);
+                    }
+                }
+                AdminAction::Broadcast(text) => {
+                    if !chat::validate(text) {
+                        return Err(GameError::InvalidMessage);
+                    }
+                }
+                AdminAction::SetAbandonedAfter(days) => {
+                    if days.checked_mul(calendar::TICKS_PER_DAY).is_none() {
+                        return Err(GameError::InvalidSetting);
+                    }
+                }
+                AdminAction::SetChatPolicy(policy) => {
+                    if !policy.validate() {
+                        return Err(GameError::InvalidSetting);
+                    }
+                }
+                AdminAction::SetCounter(_)
+                | AdminAction::SetPrivateCounter(_, _)
+                | AdminAction::UnbanPlayer(_)
+                | AdminAction::UnmutePlayer(_) => {}
+            },
+        }
+
+        Ok(())
+    }
+
     pub fn update(&mut self, EventData { event, user_id }: EventData) {
         match event {
             Event::Increment => {
@@ -115,13 +335,78 @@ impl State {
             }
             Event::IncrementPrivate => {
                 *self.cnt_private.entry(user_id.unwrap()).or_default() += 1;
-            },
+            }
             Event::Tick => {
-                self.cnt += 1;
+                self.advance_ticks(1);
+            }
+            Event::Seen(user_id) => {
+                self.last_seen.insert(user_id, self.tick);
             }
+            Event::Admin(action) => match action {
+                AdminAction::SetCounter(cnt) => {
+                    self.cnt = cnt;
+                }
+                AdminAction::SetPrivateCounter(user_id, cnt) => {
+                    self.cnt_private.insert(user_id, cnt);
+                }
+                AdminAction::BanPlayer(user_id) => {
+                    self.banned.insert(user_id);
+                }
+                AdminAction::UnbanPlayer(user_id) => {
+                    self.banned.remove(&user_id);
+                }
+                AdminAction::MutePlayer(user_id) => {
+                    self.muted.insert(user_id);
+                }
+                AdminAction::UnmutePlayer(user_id) => {
+                    self.muted.remove(&user_id);
+                }
+                // Only disconnects the player, see `AdminAction::disconnects`.
+                AdminAction::KickPlayer(_) => {}
+                AdminAction::Broadcast(_) => {}
+                AdminAction::SetAbandonedAfter(days) => {
+                    self.settings.abandoned_after_days = days;
+                }
+                AdminAction::SetChatPolicy(policy) => {
+                    self.settings.chat = policy;
+                }
+            },
+        }
+    }
+
+    // Equivalent to applying `n` tick events, without the per tick overhead.
+    // Every tick increments the counter with wrapping, so truncating `n`
+    // gives exactly the same result as `n` single increments.
+    pub fn advance_ticks(&mut self, n: u64) {
+        let day = self.tick / calendar::TICKS_PER_DAY;
+        self.tick += n;
+        self.cnt = self.cnt.wrapping_add(n as u32);
+
+        // No events happen in between, so checking once at the last day that
+        // started is the same as checking at every one of them.
+        if self.tick / calendar::TICKS_PER_DAY != day {
+            let start_of_day = self.tick - self.world_time().time_of_day();
+            self.reclaim_abandoned(start_of_day);
         }
     }
 
+    fn reclaim_abandoned(&mut self, tick: u64) {
+        let last_seen = &self.last_seen;
+        let abandoned_after = self.settings.abandoned_after();
+        self.cnt_private.retain(|user_id, _| {
+            last_seen
+                .get(user_id)
+                .is_none_or(|seen| seen.saturating_add(abandoned_after) >= tick)
+        });
+    }
+
+    // Whether the player has to be seen again to not count as inactive.
+    pub fn needs_seen(&self, user_id: UserId) -> bool {
+        self.last_seen
+            .get(&user_id)
+            .is_none_or(|seen| seen + calendar::TICKS_PER_DAY <= self.tick)
+    }
+
     pub fn view(&self, receiver: UserId) -> Self {
         State {
             cnt_private: HashMap::from_iter(
@@ -129,6 +414,15 @@ impl State {
                     .get_key_value(&receiver)
                     .map(|(&k, &v)| (k, v)),
             ),
+            last_seen: BTreeMap::from_iter(
+                self.last_seen
+                    .get_key_value(&receiver)
+                    .map(|(&k, &v)| (k, v)),
+            ),
+            // Moderation is only known to the server, muted players must not
+            // find out that their messages are dropped.
+            banned: BTreeSet::new(),
+            muted: BTreeSet::new(),
             ..self.clone()
         }
     }
@@ -139,6 +433,63 @@ pub enum Event {
     Increment,
     IncrementPrivate,
     Tick,
+    // Emitted by the server for players that connect or send events, at
+    // most once per day of the calendar, see `State::needs_seen`.
+    Seen(UserId),
+    Admin(AdminAction),
+}
+
+// Moderation of a live world, only accepted from admins.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum AdminAction {
+    // Any value is fine, the counter wraps around on the next tick.
+    SetCounter(u32),
+    // Only sent to the player whose counter it sets.
+    SetPrivateCounter(UserId, u32),
+    // Admins cannot be banned, muted or kicked. Bans and mutes are only known
+    // to the server, they are not sent to clients.
+    BanPlayer(UserId),
+    UnbanPlayer(UserId),
+    // Drops the player's chat messages.
+    MutePlayer(UserId),
+    UnmutePlayer(UserId),
+    // Disconnects the player, who may join again right away.
+    KickPlayer(UserId),
+    // A message shown to every player.
+    Broadcast(String),
+    SetAbandonedAfter(u64),
+    SetChatPolicy(chat::Policy),
+}
+
+impl AdminAction {
+    // The player whose connections have to be closed once this is applied.
+    pub fn disconnects(&self) -> Option<UserId> {
+        match self {
+            AdminAction::BanPlayer(user_id) | AdminAction::KickPlayer(user_id) => Some(*user_id),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameError {
+    Unauthorized,
+    CounterOverflow,
+    InvalidMessage,
+    AlreadySeen,
+    InvalidSetting,
+}
+
+impl Event {
+    pub fn permission(&self) -> Permission {
+        match self {
+            Event::Increment => Permission::Player,
+            Event::IncrementPrivate => Permission::Player,
+            Event::Tick => Permission::Server,
+            Event::Seen(_) => Permission::Server,
+            Event::Admin(_) => Permission::Admin,
+        }
+    }
 }
 
 impl EventData {
@@ -147,7 +498,15 @@ impl EventData {
         let user_id = *user_id;
 
         match event {
-            Event::IncrementPrivate if user_id.unwrap() != receiver => false,
+            Event::IncrementPrivate => user_id.unwrap() == receiver,
+            Event::Admin(AdminAction::SetPrivateCounter(target, _)) => *target == receiver,
+            Event::Seen(seen) => *seen == receiver,
+            Event::Admin(
+                AdminAction::BanPlayer(_)
+                | AdminAction::UnbanPlayer(_)
+                | AdminAction::MutePlayer(_)
+                | AdminAction::UnmutePlayer(_),
+            ) => false,
             _ => true,
         }
     }
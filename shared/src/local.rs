@@ -0,0 +1,130 @@
+use crate::{runner::WorldRunner, Event, EventData, State, SyncData, UserId};
+use std::time::Duration;
+
+// The local player never collides with a registered account, since sqlite
+// starts assigning user ids at 1.
+pub const LOCAL_USER_ID: UserId = 0;
+
+#[derive(Debug, Clone)]
+pub struct ScriptedEvent {
+    pub tick: u64,
+    pub event: Event,
+}
+
+// Runs the same simulation as the server without a connection, so it can be
+// driven from wasm for the tutorial.
+pub struct LocalGame {
+    runner: WorldRunner,
+}
+
+impl LocalGame {
+    pub fn new() -> Self {
+        LocalGame::with_script(Vec::new())
+    }
+
+    pub fn with_script(script: Vec<ScriptedEvent>) -> Self {
+        let mut runner = WorldRunner::new(State::default());
+        for scripted in script {
+            runner.schedule(
+                scripted.tick,
+                EventData {
+                    event: scripted.event,
+                    user_id: Some(LOCAL_USER_ID),
+                },
+            );
+        }
+
+        LocalGame { runner }
+    }
+
+    pub fn tutorial() -> Self {
+        LocalGame::with_script(vec![
+            ScriptedEvent {
+                tick: 2,
+                event: Event::Increment,
+            },
+            ScriptedEvent {
+                tick: 4,
+                event: Event::IncrementPrivate,
+            },
+        ])
+    }
+
+    pub fn state(&self) -> &State {
+        self.runner.state()
+    }
+
+    pub fn sync_data(&self) -> SyncData {
+        self.runner.sync_data(LOCAL_USER_ID)
+    }
+
+    // The event is applied with the next tick, just like on the server.
+    pub fn send(&mut self, event: Event) {
+        self.runner.push(
+            EventData {
+                event,
+                user_id: Some(LOCAL_USER_ID),
+            },
+            (),
+        );
+    }
+
+    // Feeds wall clock time into the tick driver and returns every event that
+    // was applied, in order, so the caller can render them.
+    pub fn advance(&mut self, elapsed: Duration) -> Vec<EventData> {
+        self.runner.advance(elapsed);
+        self.process()
+    }
+
+    pub fn tick(&mut self) -> Vec<EventData> {
+        self.runner.tick();
+        self.process()
+    }
+
+    fn process(&mut self) -> Vec<EventData> {
+        self.runner
+            .process()
+            .into_iter()
+            .filter(|processed| processed.result.is_ok())
+            .map(|processed| processed.event)
+            .collect()
+    }
+}
+
+impl Default for LocalGame {
+    fn default() -> Self {
+        LocalGame::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tutorial_runs_on_schedule() {
+        let mut game = LocalGame::tutorial();
+        for _ in 0..3 {
+            game.tick();
+        }
+        // Every tick increments the counter as well.
+        assert_eq!(game.state().cnt, 4);
+        assert!(game.state().cnt_private.is_empty());
+
+        for _ in 0..2 {
+            game.tick();
+        }
+        assert_eq!(game.state().cnt_private[&LOCAL_USER_ID], 1);
+    }
+
+    #[test]
+    fn sent_events_wait_for_the_next_tick() {
+        let mut game = LocalGame::new();
+        game.send(Event::IncrementPrivate);
+        assert!(game.state().cnt_private.is_empty());
+
+        let applied = game.tick();
+        assert_eq!(applied.len(), 2);
+        assert_eq!(game.state().cnt_private[&LOCAL_USER_ID], 1);
+    }
+}
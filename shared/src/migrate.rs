@@ -0,0 +1,168 @@
+use crate::snapshot::Snapshot;
+use serde::{Deserialize, Serialize};
+
+pub const STATE_VERSION: u32 = 3;
+
+// Every layout the world has ever been saved in. The variant is the version
+// tag of a save, so existing variants must never be renamed or changed.
+// When the state changes incompatibly, freeze the old types in a new `vN`
+// module below, add a variant for the new layout and extend `migrate`.
+#[derive(Serialize, Deserialize)]
+pub enum VersionedSnapshot {
+    V0(v0::State),
+    V1(v1::Snapshot),
+    V2(v2::Snapshot),
+    V3(Snapshot),
+}
+
+impl VersionedSnapshot {
+    pub fn version(&self) -> u32 {
+        match self {
+            VersionedSnapshot::V0(_) => 0,
+            VersionedSnapshot::V1(_) => 1,
+            VersionedSnapshot::V2(_) => 2,
+            VersionedSnapshot::V3(_) => 3,
+        }
+    }
+
+    // Reads a saved world in any layout it has ever been stored in. Worlds
+    // saved before versioning was introduced are not tagged.
+    pub fn decode(data: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        rmp_serde::from_slice(data)
+            .or_else(|_| rmp_serde::from_slice(data).map(VersionedSnapshot::V1))
+            .or_else(|_| rmp_serde::from_slice(data).map(VersionedSnapshot::V0))
+    }
+
+    pub fn migrate(self) -> Snapshot {
+        match self {
+            VersionedSnapshot::V0(state) => VersionedSnapshot::V1(state.into()).migrate(),
+            VersionedSnapshot::V1(snapshot) => VersionedSnapshot::V2(snapshot.into()).migrate(),
+            VersionedSnapshot::V2(snapshot) => VersionedSnapshot::V3(snapshot.into()).migrate(),
+            VersionedSnapshot::V3(snapshot) => snapshot,
+        }
+    }
+}
+
+impl From<Snapshot> for VersionedSnapshot {
+    fn from(snapshot: Snapshot) -> Self {
+        VersionedSnapshot::V3(snapshot)
+    }
+}
+
+// The state as it was stored before worlds were saved as snapshots.
+pub mod v0 {
+    use crate::UserId;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize)]
+    pub struct State {
+        pub cnt: u32,
+        pub cnt_private: HashMap<UserId, u32>,
+    }
+
+    impl From<State> for super::v1::Snapshot {
+        fn from(State { cnt, cnt_private }: State) -> Self {
+            super::v1::Snapshot {
+                tick: 0,
+                state: super::v1::State {
+                    cnt,
+                    cnt_private,
+                    tick: 0,
+                },
+            }
+        }
+    }
+}
+
+// The snapshots before admins, moderation, last-seen ticks and settings were
+// added to the state.
+pub mod v1 {
+    use crate::UserId;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize)]
+    pub struct State {
+        pub cnt: u32,
+        pub cnt_private: HashMap<UserId, u32>,
+        pub tick: u64,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct Snapshot {
+        pub tick: u64,
+        pub state: State,
+    }
+
+    impl From<Snapshot> for super::v2::Snapshot {
+        fn from(Snapshot { tick, state }: Snapshot) -> Self {
+            super::v2::Snapshot {
+                tick,
+                state: super::v2::State {
+                    cnt: state.cnt,
+                    cnt_private: state.cnt_private,
+                    tick: state.tick,
+                    admins: Default::default(),
+                    banned: Default::default(),
+                    muted: Default::default(),
+                    last_seen: Default::default(),
+                    settings: super::v2::Settings {
+                        abandoned_after_days: 30,
+                    },
+                },
+            }
+        }
+    }
+}
+
+// The snapshots before worlds had a chat policy.
+pub mod v2 {
+    use crate::UserId;
+    use serde::{Deserialize, Serialize};
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+    #[derive(Serialize, Deserialize)]
+    pub struct Settings {
+        pub abandoned_after_days: u64,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct State {
+        pub cnt: u32,
+        pub cnt_private: HashMap<UserId, u32>,
+        pub tick: u64,
+        pub admins: BTreeSet<UserId>,
+        pub banned: BTreeSet<UserId>,
+        pub muted: BTreeSet<UserId>,
+        pub last_seen: BTreeMap<UserId, u64>,
+        pub settings: Settings,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct Snapshot {
+        pub tick: u64,
+        pub state: State,
+    }
+
+    impl From<Snapshot> for crate::snapshot::Snapshot {
+        fn from(Snapshot { tick, state }: Snapshot) -> Self {
+            crate::snapshot::Snapshot {
+                tick,
+                state: crate::State {
+                    cnt: state.cnt,
+                    cnt_private: state.cnt_private,
+                    tick: state.tick,
+                    admins: state.admins,
+                    banned: state.banned,
+                    muted: state.muted,
+                    last_seen: state.last_seen,
+                    settings: crate::Settings {
+                        abandoned_after_days: state.settings.abandoned_after_days,
+                        ..Default::default()
+                    },
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{EventData, State};
+use std::collections::VecDeque;
+
+// Steps through a journal of applied events, together with the tick each one
+// was applied at, to find out how a world ended up in its current state.
+pub struct Replay {
+    state: State,
+    journal: VecDeque<(u64, EventData)>,
+}
+
+impl Replay {
+    pub fn new(state: State, journal: impl IntoIterator<Item = (u64, EventData)>) -> Self {
+        Replay {
+            state,
+            journal: journal.into_iter().collect(),
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    // Applies the next event. Ticks that the journal skips, like the ones the
+    // server caught up on after a restart, are fast forwarded first.
+    pub fn step(&mut self) -> Option<EventData> {
+        let (tick, event) = self.journal.pop_front()?;
+        if tick > self.state.tick {
+            self.state.advance_ticks(tick - self.state.tick);
+        }
+        self.state.update(event.clone());
+        Some(event)
+    }
+
+    // Applies events until the state reaches `tick`. Returns false if the
+    // journal ends before that.
+    pub fn run_to(&mut self, tick: u64) -> bool {
+        while self.state.tick < tick {
+            if self.step().is_none() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Describes every difference between two states, one line each.
+pub fn diff(before: &State, after: &State) -> Vec<String> {
+    let State {
+        cnt,
+        cnt_private,
+        tick,
+        admins,
+        banned,
+        muted,
+        last_seen,
+        settings,
+    } = before;
+
+    let mut changes = Vec::new();
+    if *tick != after.tick {
+        changes.push(format!("tick: {} -> {}", tick, after.tick));
+    }
+    if *cnt != after.cnt {
+        changes.push(format!("cnt: {} -> {}", cnt, after.cnt));
+    }
+
+    let mut user_ids: Vec<_> = cnt_private.keys().chain(after.cnt_private.keys()).collect();
+    user_ids.sort_unstable();
+    user_ids.dedup();
+    for user_id in user_ids {
+        let (old, new) = (cnt_private.get(user_id), after.cnt_private.get(user_id));
+        if old != new {
+            changes.push(format!("cnt_private[{}]: {:?} -> {:?}", user_id, old, new));
+        }
+    }
+
+    if *admins != after.admins {
+        changes.push(format!("admins: {:?} -> {:?}", admins, after.admins));
+    }
+    if *banned != after.banned {
+        changes.push(format!("banned: {:?} -> {:?}", banned, after.banned));
+    }
+    if *muted != after.muted {
+        changes.push(format!("muted: {:?} -> {:?}", muted, after.muted));
+    }
+    for (user_id, tick) in &after.last_seen {
+        if last_seen.get(user_id) != Some(tick) {
+            changes.push(format!("last_seen[{}]: {}", user_id, tick));
+        }
+    }
+
+    if *settings != after.settings {
+        changes.push(format!("settings: {:?} -> {:?}", settings, after.settings));
+    }
+
+    changes
+}
@@ -0,0 +1,373 @@
+#[cfg(feature = "debug")]
+use crate::history::History;
+use crate::{Event, EventData, GameError, State, SyncData, UserId, TICK_INTERVAL};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+
+pub const MIN_SPEED: f64 = 0.1;
+pub const MAX_SPEED: f64 = 100.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TickControl {
+    Pause,
+    Resume,
+    // Schedules a single tick, also while paused.
+    Step,
+    // Multiplier of the default tick rate, clamped to `MIN_SPEED..=MAX_SPEED`.
+    SetSpeed(f64),
+}
+
+// Every tick is processed in these phases, in order. Player events that
+// arrived since the previous tick are buffered and applied together at the
+// start of the next one, sorted by player and then by arrival, so the outcome
+// doesn't depend on how the events of different players interleaved on the
+// network.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Player,
+    // Events scheduled by the world itself for this tick.
+    Scheduled,
+    // The tick event, which advances the simulation.
+    Simulation,
+    // Reserved for systems that tidy up after the simulation, there are none yet.
+    Cleanup,
+}
+
+// Drives a world: collects incoming events, schedules ticks and applies both
+// phase by phase. Transports only have to feed it events and elapsed time,
+// and pass on what comes out of `process_next`. While paused, events wait in
+// the inbox until the next tick.
+//
+// `T` identifies where an event came from, e.g. the connection to report a
+// rejection to. Events created by the runner get `T::default()`.
+pub struct WorldRunner<T = ()> {
+    state: State,
+    inbox: Vec<Arrival<T>>,
+    next_seq: u64,
+    scheduled: BTreeMap<u64, Vec<EventData>>,
+    // The events of the tick currently being processed.
+    queue: VecDeque<(Phase, EventData, T)>,
+    elapsed: Duration,
+    pending_ticks: u64,
+    paused: bool,
+    tick_interval: Duration,
+    #[cfg(feature = "debug")]
+    history: Option<History>,
+}
+
+struct Arrival<T> {
+    // The tick the event arrived at.
+    tick: u64,
+    // Counts up over all arrivals, so events keep their order per player.
+    seq: u64,
+    event: EventData,
+    origin: T,
+}
+
+pub struct Processed<T> {
+    // The tick the event was applied at.
+    pub tick: u64,
+    pub phase: Phase,
+    pub event: EventData,
+    pub origin: T,
+    pub result: Result<(), GameError>,
+}
+
+impl<T: Default> WorldRunner<T> {
+    pub fn new(state: State) -> Self {
+        WorldRunner {
+            state,
+            inbox: Vec::new(),
+            next_seq: 0,
+            scheduled: BTreeMap::new(),
+            queue: VecDeque::new(),
+            elapsed: Duration::ZERO,
+            pending_ticks: 0,
+            paused: false,
+            tick_interval: TICK_INTERVAL,
+            #[cfg(feature = "debug")]
+            history: None,
+        }
+    }
+
+    // Records every event applied from now on, so the world can be rewound.
+    #[cfg(feature = "debug")]
+    pub fn record_history(&mut self, history: History) {
+        self.history = Some(history);
+    }
+
+    #[cfg(feature = "debug")]
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    // Replaces the state with the one at the start of `tick`. The recorded
+    // history starts over, since the events from `tick` on no longer happened.
+    #[cfg(feature = "debug")]
+    pub fn rewind_to(&mut self, tick: u64) -> bool {
+        let history = match &mut self.history {
+            Some(history) => history,
+            None => return false,
+        };
+        if !self.state.rewind_to(history, tick) {
+            return false;
+        }
+        history.clear();
+        true
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn tick_interval(&self) -> Duration {
+        self.tick_interval
+    }
+
+    pub fn speed(&self) -> f64 {
+        TICK_INTERVAL.as_secs_f64() / self.tick_interval.as_secs_f64()
+    }
+
+    pub fn control(&mut self, control: TickControl) {
+        match control {
+            TickControl::Pause => {
+                self.paused = true;
+            }
+            TickControl::Resume => {
+                self.paused = false;
+            }
+            TickControl::Step => {
+                self.tick();
+            }
+            TickControl::SetSpeed(speed) => {
+                if !speed.is_nan() {
+                    self.tick_interval = TICK_INTERVAL.div_f64(speed.clamp(MIN_SPEED, MAX_SPEED));
+                }
+            }
+        }
+    }
+
+    pub fn sync_data(&self, receiver: UserId) -> SyncData {
+        SyncData {
+            user_id: receiver,
+            state: self.state.view(receiver),
+        }
+    }
+
+    // Buffers a player event until the next tick.
+    pub fn push(&mut self, event: EventData, origin: T) {
+        self.inbox.push(Arrival {
+            tick: self.state.tick,
+            seq: self.next_seq,
+            event,
+            origin,
+        });
+        self.next_seq += 1;
+    }
+
+    // Applies an event in the scheduled phase of the given tick, or of the
+    // next tick if that one has already passed.
+    pub fn schedule(&mut self, tick: u64, event: EventData) {
+        self.scheduled.entry(tick).or_default().push(event);
+    }
+
+    pub fn tick(&mut self) {
+        self.pending_ticks += 1;
+    }
+
+    // Schedules a tick for every full tick interval that has passed and
+    // returns how many were scheduled. Time passing while paused is ignored.
+    pub fn advance(&mut self, elapsed: Duration) -> u64 {
+        if self.paused {
+            return 0;
+        }
+        self.elapsed += elapsed;
+
+        let mut ticks = 0;
+        while self.elapsed >= self.tick_interval {
+            self.elapsed -= self.tick_interval;
+            self.tick();
+            ticks += 1;
+        }
+        ticks
+    }
+
+    // Lines up all events of the next tick, phase by phase.
+    fn begin_tick(&mut self) {
+        let mut arrivals = std::mem::take(&mut self.inbox);
+        arrivals.sort_by_key(|arrival| (arrival.tick, arrival.event.user_id, arrival.seq));
+        for arrival in arrivals {
+            self.queue
+                .push_back((Phase::Player, arrival.event, arrival.origin));
+        }
+
+        let later = self.scheduled.split_off(&(self.state.tick + 1));
+        for event in std::mem::replace(&mut self.scheduled, later)
+            .into_values()
+            .flatten()
+        {
+            self.queue
+                .push_back((Phase::Scheduled, event, T::default()));
+        }
+
+        let tick = EventData {
+            event: Event::Tick,
+            user_id: None,
+        };
+        self.queue
+            .push_back((Phase::Simulation, tick, T::default()));
+    }
+
+    pub fn process_next(&mut self) -> Option<Processed<T>> {
+        if self.queue.is_empty() && self.pending_ticks > 0 {
+            self.pending_ticks -= 1;
+            self.begin_tick();
+        }
+        let (phase, event, origin) = self.queue.pop_front()?;
+
+        let tick = self.state.tick;
+        let result = self.state.validate(&event);
+        if result.is_ok() {
+            #[cfg(feature = "debug")]
+            if let Some(history) = &mut self.history {
+                history.record(&self.state, &event);
+            }
+            self.state.update(event.clone());
+        }
+
+        Some(Processed {
+            tick,
+            phase,
+            event,
+            origin,
+            result,
+        })
+    }
+
+    pub fn process(&mut self) -> Vec<Processed<T>> {
+        std::iter::from_fn(|| self.process_next()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{self, ADMIN},
+        AdminAction,
+    };
+
+    fn event(event: Event, user_id: UserId) -> EventData {
+        EventData {
+            event,
+            user_id: Some(user_id),
+        }
+    }
+
+    // The actions of the admin conflict with the increments of the others,
+    // so applying them in a different order gives a different state.
+    fn runner() -> WorldRunner {
+        WorldRunner::new(fixtures::empty(0))
+    }
+
+    fn sent() -> Vec<Vec<EventData>> {
+        vec![
+            vec![
+                event(Event::Admin(AdminAction::SetCounter(10)), ADMIN),
+                event(Event::Admin(AdminAction::SetPrivateCounter(2, 5)), ADMIN),
+            ],
+            vec![
+                event(Event::Increment, 2),
+                event(Event::IncrementPrivate, 2),
+            ],
+            vec![event(Event::Increment, 3), event(Event::Increment, 3)],
+        ]
+    }
+
+    // Pushes the events of every player in order, but interleaved with the
+    // other players as given by `order`, then runs a tick.
+    fn run(order: &[usize]) -> (State, Vec<EventData>) {
+        let mut runner = runner();
+        let mut sent: Vec<VecDeque<_>> = sent().into_iter().map(VecDeque::from).collect();
+        for &player in order {
+            runner.push(sent[player].pop_front().unwrap(), ());
+        }
+        runner.tick();
+        let applied = runner
+            .process()
+            .into_iter()
+            .map(|processed| processed.event)
+            .collect();
+        (runner.state().clone(), applied)
+    }
+
+    #[test]
+    fn arrival_order_does_not_matter() {
+        let (state, applied) = run(&[0, 0, 1, 1, 2, 2]);
+        for order in [[2, 1, 0, 2, 1, 0], [1, 2, 2, 1, 0, 0], [2, 2, 1, 1, 0, 0]] {
+            let (other, other_applied) = run(&order);
+            assert_eq!(state.checksum(), other.checksum());
+            assert_eq!(format!("{:?}", applied), format!("{:?}", other_applied));
+        }
+        // Sorted by player, each in the order they were sent.
+        let expected: Vec<_> = sent().into_iter().flatten().collect();
+        assert_eq!(format!("{:?}", &applied[..6]), format!("{:?}", expected));
+    }
+
+    #[test]
+    fn phases_apply_in_order() {
+        let mut runner = runner();
+        runner.schedule(0, event(Event::Increment, 3));
+        runner.push(event(Event::IncrementPrivate, 2), ());
+        runner.tick();
+        let phases: Vec<_> = runner.process().iter().map(|p| p.phase).collect();
+        assert_eq!(phases, [Phase::Player, Phase::Scheduled, Phase::Simulation]);
+    }
+
+    #[test]
+    fn events_wait_for_the_next_tick() {
+        let mut runner = runner();
+        runner.push(event(Event::Increment, 2), ());
+        runner.schedule(1, event(Event::Increment, 3));
+        assert!(runner.process().is_empty());
+
+        runner.tick();
+        let processed = runner.process();
+        assert_eq!(processed.len(), 2);
+        assert!(processed.iter().all(|processed| processed.tick == 0));
+
+        // Scheduled for tick 1, which has just started.
+        runner.tick();
+        assert_eq!(runner.process().len(), 2);
+        assert_eq!(runner.state().tick, 2);
+    }
+
+    #[test]
+    fn pause_step_and_speed() {
+        let mut runner = runner();
+        assert_eq!(runner.advance(TICK_INTERVAL.mul_f64(3.5)), 3);
+        // The remaining half interval is kept.
+        assert_eq!(runner.advance(TICK_INTERVAL / 2), 1);
+
+        runner.control(TickControl::Pause);
+        assert_eq!(runner.advance(TICK_INTERVAL * 10), 0);
+        runner.control(TickControl::Step);
+        runner.process();
+        assert_eq!(runner.state().tick, 5);
+
+        runner.control(TickControl::Resume);
+        runner.control(TickControl::SetSpeed(2.0));
+        assert_eq!(runner.advance(TICK_INTERVAL), 2);
+        runner.control(TickControl::SetSpeed(f64::INFINITY));
+        assert_eq!(runner.speed(), MAX_SPEED);
+        runner.process();
+        assert_eq!(runner.state().tick, 7);
+    }
+}
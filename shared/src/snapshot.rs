@@ -0,0 +1,27 @@
+use crate::{EventData, State};
+use serde::{Deserialize, Serialize};
+
+// Snapshots are taken right after a tick starts, so every journaled event
+// that was applied at `tick` or later is not yet contained in `state`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub tick: u64,
+    pub state: State,
+}
+
+impl State {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            tick: self.tick,
+            state: self.clone(),
+        }
+    }
+
+    pub fn replay(snapshot: Snapshot, events: impl IntoIterator<Item = EventData>) -> State {
+        let mut state = snapshot.state;
+        for event in events {
+            state.update(event);
+        }
+        state
+    }
+}
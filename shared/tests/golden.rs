@@ -0,0 +1,302 @@
+// Golden files of everything that is sent to clients or saved to the
+// database. If one of these fails, live clients or existing worlds can no
+// longer be decoded: bump `PROTOCOL_VERSION` or `STATE_VERSION` (freezing the
+// old layout in `migrate`), then regenerate with `UPDATE_GOLDEN=1 cargo test`.
+
+use serde::{de::DeserializeOwned, Serialize};
+use shared::{
+    chat::{Channel, Policy},
+    desync::DesyncReport,
+    fixtures,
+    migrate::{v0, v1, v2, VersionedSnapshot},
+    AdminAction, Event, EventData, GameError, Req, Res, Settings, State, SyncData,
+};
+use std::{collections::HashMap, env, fs, path::PathBuf, time::Duration};
+
+fn path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.msgpack", name))
+}
+
+fn check<T: Serialize + DeserializeOwned>(name: &str, value: &T) {
+    let path = path(name);
+    let bytes = rmp_serde::to_vec(value).unwrap();
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &bytes).unwrap();
+    }
+
+    let golden =
+        fs::read(&path).unwrap_or_else(|err| panic!("cannot read {}: {}", path.display(), err));
+    if let Err(err) = rmp_serde::from_slice::<T>(&golden) {
+        panic!("{} can no longer be decoded: {}", name, err);
+    }
+    assert_eq!(
+        hex(&golden),
+        hex(&bytes),
+        "{} is encoded differently than before",
+        name
+    );
+}
+
+// Checks the golden file of a saved world, then loads it like the server.
+fn load<T: Serialize + DeserializeOwned>(name: &str, value: &T) -> VersionedSnapshot {
+    check(name, value);
+    let data = fs::read(path(name)).unwrap();
+    VersionedSnapshot::decode(&data)
+        .unwrap_or_else(|err| panic!("{} can no longer be loaded: {}", name, err))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Adding a variant to any of these fails to compile until it is named here,
+// and thereby gets a golden file.
+fn event_name(event: &Event) -> String {
+    match event {
+        Event::Increment => "increment".to_owned(),
+        Event::IncrementPrivate => "increment_private".to_owned(),
+        Event::Tick => "tick".to_owned(),
+        Event::Seen(_) => "seen".to_owned(),
+        Event::Admin(action) => format!("admin_{}", admin_action_name(action)),
+    }
+}
+
+fn admin_action_name(action: &AdminAction) -> &'static str {
+    match action {
+        AdminAction::SetCounter(_) => "set_counter",
+        AdminAction::SetPrivateCounter(_, _) => "set_private_counter",
+        AdminAction::BanPlayer(_) => "ban_player",
+        AdminAction::UnbanPlayer(_) => "unban_player",
+        AdminAction::MutePlayer(_) => "mute_player",
+        AdminAction::UnmutePlayer(_) => "unmute_player",
+        AdminAction::KickPlayer(_) => "kick_player",
+        AdminAction::Broadcast(_) => "broadcast",
+        AdminAction::SetAbandonedAfter(_) => "set_abandoned_after",
+        AdminAction::SetChatPolicy(_) => "set_chat_policy",
+    }
+}
+
+fn req_name(req: &Req) -> &'static str {
+    match req {
+        Req::Join => "join",
+        Req::Resume { .. } => "resume",
+        Req::Event(_, _) => "event",
+        Req::Chat { .. } => "chat",
+        Req::ReportDesync(_) => "report_desync",
+    }
+}
+
+fn res_name(res: &Res) -> &'static str {
+    match res {
+        Res::Hello { .. } => "hello",
+        Res::Sync(_) => "sync",
+        Res::Resumed => "resumed",
+        Res::Event(_, _) => "event",
+        Res::Rejected(_, _) => "rejected",
+        Res::Chat { .. } => "chat",
+        Res::Throttled { .. } => "throttled",
+        Res::Checksum { .. } => "checksum",
+    }
+}
+
+fn game_error_name(error: &GameError) -> &'static str {
+    match error {
+        GameError::Unauthorized => "unauthorized",
+        GameError::CounterOverflow => "counter_overflow",
+        GameError::InvalidMessage => "invalid_message",
+        GameError::AlreadySeen => "already_seen",
+        GameError::InvalidSetting => "invalid_setting",
+    }
+}
+
+#[test]
+fn events() {
+    let events = [
+        Event::Increment,
+        Event::IncrementPrivate,
+        Event::Tick,
+        Event::Seen(2),
+        Event::Admin(AdminAction::SetCounter(u32::MAX)),
+        Event::Admin(AdminAction::SetPrivateCounter(2, 5)),
+        Event::Admin(AdminAction::BanPlayer(3)),
+        Event::Admin(AdminAction::UnbanPlayer(3)),
+        Event::Admin(AdminAction::MutePlayer(4)),
+        Event::Admin(AdminAction::UnmutePlayer(4)),
+        Event::Admin(AdminAction::KickPlayer(2)),
+        Event::Admin(AdminAction::Broadcast("Restarting soon".to_owned())),
+        Event::Admin(AdminAction::SetAbandonedAfter(14)),
+        Event::Admin(AdminAction::SetChatPolicy(Policy {
+            blocked_words: vec!["darn".to_owned()],
+            strip_links: true,
+        })),
+    ];
+    for event in events {
+        check(&format!("event_{}", event_name(&event)), &event);
+    }
+}
+
+#[test]
+fn game_errors() {
+    let errors = [
+        GameError::Unauthorized,
+        GameError::CounterOverflow,
+        GameError::InvalidMessage,
+        GameError::AlreadySeen,
+        GameError::InvalidSetting,
+    ];
+    for error in errors {
+        check(&format!("game_error_{}", game_error_name(&error)), &error);
+    }
+}
+
+#[test]
+fn requests() {
+    let requests = [
+        Req::Join,
+        Req::Resume {
+            token: 0xdead_beef,
+            last_tick: 1500,
+        },
+        Req::Event(9, Event::IncrementPrivate),
+        Req::Chat {
+            channel: Channel::Private(3),
+            text: "hi".to_owned(),
+        },
+        Req::ReportDesync(DesyncReport {
+            tick: 1500,
+            server: fixtures::populated().sections(),
+            client: fixtures::populated().view(2).sections(),
+            events: vec![EventData {
+                event: Event::IncrementPrivate,
+                user_id: Some(2),
+            }],
+        }),
+    ];
+    for req in requests {
+        check(&format!("req_{}", req_name(&req)), &req);
+    }
+}
+
+#[test]
+fn responses() {
+    let responses = [
+        Res::Hello {
+            protocol_version: shared::PROTOCOL_VERSION,
+            resume_token: 0xdead_beef,
+        },
+        Res::Sync(SyncData {
+            user_id: 2,
+            state: fixtures::populated(),
+        }),
+        Res::Resumed,
+        Res::Event(
+            EventData {
+                event: Event::Increment,
+                user_id: Some(2),
+            },
+            Some(9),
+        ),
+        Res::Rejected(9, GameError::CounterOverflow),
+        Res::Chat {
+            channel: Channel::Global,
+            from: 2,
+            text: "hi".to_owned(),
+            tick: 1500,
+        },
+        Res::Throttled {
+            event_id: Some(9),
+            retry_after: Duration::from_millis(250),
+        },
+        Res::Checksum {
+            tick: 1500,
+            sections: fixtures::populated().view(2).sections(),
+        },
+    ];
+    for res in responses {
+        check(&format!("res_{}", res_name(&res)), &res);
+    }
+}
+
+#[test]
+fn snapshots() {
+    check("state", &fixtures::populated());
+
+    let current = load(
+        "snapshot_v3",
+        &VersionedSnapshot::from(fixtures::populated().snapshot()),
+    );
+    assert_eq!(current.version(), 3);
+    assert_eq!(
+        current.migrate().state.checksum(),
+        fixtures::populated().checksum()
+    );
+
+    let v2 = v2::Snapshot {
+        tick: 1500,
+        state: v2::State {
+            cnt: 42,
+            cnt_private: HashMap::from([(2, 7)]),
+            tick: 1500,
+            admins: [1].into(),
+            banned: [3].into(),
+            muted: [4].into(),
+            last_seen: [(2, 1440)].into(),
+            settings: v2::Settings {
+                abandoned_after_days: 14,
+            },
+        },
+    };
+    let expected = State {
+        settings: Settings {
+            chat: Policy::default(),
+            ..fixtures::populated().settings
+        },
+        ..fixtures::populated()
+    };
+    let tagged = load("snapshot_v2", &VersionedSnapshot::V2(v2));
+    assert_eq!(tagged.version(), 2);
+    assert_eq!(tagged.migrate().state.checksum(), expected.checksum());
+
+    let v1 = || v1::Snapshot {
+        tick: 1500,
+        state: v1::State {
+            cnt: 42,
+            cnt_private: HashMap::from([(2, 7)]),
+            tick: 1500,
+        },
+    };
+    let expected = State {
+        cnt: 42,
+        cnt_private: HashMap::from([(2, 7)]),
+        tick: 1500,
+        ..Default::default()
+    };
+    let tagged = load("snapshot_v1", &VersionedSnapshot::V1(v1()));
+    assert_eq!(tagged.version(), 1);
+    assert_eq!(tagged.migrate().state.checksum(), expected.checksum());
+    // Worlds saved before versioning was introduced are not tagged.
+    let untagged = load("snapshot_v1_untagged", &v1());
+    assert_eq!(untagged.version(), 1);
+    let migrated = untagged.migrate();
+    assert_eq!(migrated.tick, 1500);
+    assert_eq!(migrated.state.checksum(), expected.checksum());
+
+    let v0 = || v0::State {
+        cnt: 42,
+        cnt_private: HashMap::from([(2, 7)]),
+    };
+    let expected = State {
+        tick: 0,
+        ..expected
+    };
+    let tagged = load("snapshot_v0", &VersionedSnapshot::V0(v0()));
+    assert_eq!(tagged.version(), 0);
+    assert_eq!(tagged.migrate().state.checksum(), expected.checksum());
+    let untagged = load("snapshot_v0_untagged", &v0());
+    assert_eq!(untagged.version(), 0);
+    let migrated = untagged.migrate();
+    assert_eq!(migrated.state.checksum(), expected.checksum());
+}